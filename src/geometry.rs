@@ -1,5 +1,9 @@
+pub use hit::Hit;
+pub use instance::Instance;
 pub use matrix::Mat4f;
 pub use point::{Point3d, Point4d};
+pub use quaternion::Quaternion;
+pub use ray::Ray;
 pub use vector::Vector3d;
 
 use crate::geometry::aabb::Aabb;
@@ -7,10 +11,19 @@ use crate::geometry::aabb::Aabb;
 pub(crate) mod matrix;
 mod vector;
 mod point;
+mod quaternion;
+mod hit;
+mod instance;
+mod ray;
 pub mod triangle;
-// pub mod axis_aligned_box;
-// pub mod plane;
+pub mod plane;
+pub mod disk;
+pub mod cuboid;
 pub mod sphere;
+pub mod cylinder;
+pub mod cone;
+pub mod torus;
+mod roots;
 pub(crate) mod aabb;
 
 
@@ -42,8 +55,17 @@ fn max_of_three_f32(a: f32, b: f32, c: f32) -> f32 {
 	max_of_two_f32(a, max_of_two_f32(b, c))
 }
 
-pub trait TraceablePrimitive {
-	fn get_distance_to(&self, ray_origin: &Point3d, ray_dir: &Vector3d) -> Option<f32>;
+/// Primitives are shared between the rendering threads, hence Send + Sync
+pub trait TraceablePrimitive: Send + Sync {
+	/// Distance along the ray to the closest hit within the ray's [tmin; tmax] interval
+	fn get_distance_to(&self, ray: &Ray) -> Option<f32>;
+	/// The hit record of the closest hit. The primitive and the object indices are left
+	/// for the owner of the primitive to fill in.
+	fn get_hit(&self, ray: &Ray) -> Option<Hit> {
+		let distance = self.get_distance_to(ray)?;
+		let point = ray.get_point_at(distance);
+		Some(Hit::new(distance, point, self.get_normal(&point)))
+	}
 	fn get_normal(&self, surface_pt: &Point3d) -> Vector3d;
 	fn get_bounding_box(&self) -> Aabb;
 }
@@ -1,5 +1,4 @@
-use crate::geometry::{Point3d, Vector3d, min_of_two_f32, max_of_two_f32};
-use std::mem;
+use crate::geometry::{Point3d, Ray, Vector3d, min_of_two_f32, max_of_two_f32};
 use crate::geometry::TraceablePrimitive;
 
 #[derive(Copy, Clone)]
@@ -16,6 +15,13 @@ impl Aabb {
 			max: Point3d::from_coords(f32::MIN, f32::MIN, f32::MIN),
 		}
 	}
+	/// The box of the unbounded primitives, e.g. planes
+	pub fn infinite() -> Aabb {
+		Aabb {
+			min: Point3d::from_coords(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+			max: Point3d::from_coords(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+		}
+	}
 	pub fn from_point3d(min: Point3d, max: Point3d) -> Aabb {
 		Aabb {
 			min,
@@ -23,13 +29,18 @@ impl Aabb {
 		}
 	}
 	
-	pub fn _get_min(&self) -> Point3d {
+	pub fn get_min(&self) -> Point3d {
 		self.min
 	}
-	pub fn _get_max(&self) -> Point3d {
+	pub fn get_max(&self) -> Point3d {
 		self.max
 	}
 	
+	/// Infinite boxes have no centroid, so they can't be put into the BVH
+	pub fn is_finite(&self) -> bool {
+		(0..3).all(|i| self.min[i].is_finite() && self.max[i].is_finite())
+	}
+	
 	pub fn get_superset(&self, other: Self) -> Self {
 		Aabb::from_point3d(
 			Point3d::from_coords(
@@ -54,55 +65,41 @@ impl Aabb {
 	}
 }
 
-// TODO: Use the implementation from "An Efficient and Robust Ray-Box Intersection Algorithm" by Williams et al
+/// 1 + 2 * gamma(3) from "Robust BVH Ray Traversal" by Ize. Scaling the far distance by it
+/// keeps the slab test conservative in spite of the rounding errors, so rays never slip
+/// between the boxes of adjacent BVH nodes.
+const ROBUST_FAR_FACTOR: f32 = 1.0 + 2.0 * (3.0 * f32::EPSILON * 0.5) / (1.0 - 3.0 * f32::EPSILON * 0.5);
+
+/// The slab method from "An Efficient and Robust Ray-Box Intersection Algorithm" by Williams et al.
+/// Returns the distance where the ray enters the box, clamped to the ray's [tmin; tmax] interval,
+/// so a ray starting inside the box gets its tmin.
 impl TraceablePrimitive for Aabb {
-	fn get_distance_to(&self, ray_origin: &Point3d, ray_dir: &Vector3d) -> Option<f32> {
-		
-		let mut tmin = (self.min.x - ray_origin.x) / ray_dir.x;
-		let mut tmax = (self.max.x - ray_origin.x) / ray_dir.x;
-		if tmin > tmax {
-			mem::swap(&mut tmin, &mut tmax);
-		}
-		
-		let mut tymin = (self.min.y - ray_origin.y) / ray_dir.y;
-		let mut tymax = (self.max.y - ray_origin.y) / ray_dir.y;
-		if tymin > tymax {
-			mem::swap(&mut tymin, &mut tymax);
-		}
-		
-		if tmin > tymax || tymin > tmax {
-			return None;
-		}
-		
-		if tymin > tmin {
-			tmin = tymin;
-		}
-		if tymax < tmax {
-			tmax = tymax;
-		}
-		
-		let mut tzmin = (self.min.z - ray_origin.z) / ray_dir.z;
-		let mut tzmax = (self.max.z - ray_origin.z) / ray_dir.z;
-		if tzmin > tzmax {
-			mem::swap(&mut tzmin, &mut tzmax);
-		}
-		
-		if tmin > tzmax || tzmin > tmax {
-			return None;
-		}
+	fn get_distance_to(&self, ray: &Ray) -> Option<f32> {
+		let bounds = [self.min, self.max];
+		let orig = [ray.orig.x, ray.orig.y, ray.orig.z];
+		let dir = [ray.dir.x, ray.dir.y, ray.dir.z];
+		let dir_inv = [ray.dir_inv.x, ray.dir_inv.y, ray.dir_inv.z];
 		
-		if tzmin > tmin {
-			tmin = tzmin;
-		}
-		if tzmax < tmax {
-			tmax = tzmax;
-		}
-		
-		if tmin >= 0.0 {
-			Some(tmin)
-		} else {
-			Some(tmax)
+		let mut tmin = ray.tmin;
+		let mut tmax = ray.tmax;
+		for axis in 0..3 {
+			if dir[axis] == 0.0 {
+				// The ray is parallel to the slab, (bound - orig) * inf would be NaN on its edge
+				if orig[axis] < self.min[axis] || orig[axis] > self.max[axis] {
+					return None;
+				}
+				continue;
+			}
+			let t_near = (bounds[ray.sign[axis]][axis] - orig[axis]) * dir_inv[axis];
+			let t_far = (bounds[1 - ray.sign[axis]][axis] - orig[axis]) * dir_inv[axis] * ROBUST_FAR_FACTOR;
+			tmin = max_of_two_f32(tmin, t_near);
+			tmax = min_of_two_f32(tmax, t_far);
+			// Also rejects the boxes behind the origin and beyond the closest hit found so far
+			if tmin > tmax {
+				return None;
+			}
 		}
+		Some(tmin)
 	}
 	
 	//fn intersect (&self, ray r)
@@ -115,3 +112,56 @@ impl TraceablePrimitive for Aabb {
 		*self
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn unit_box() -> Aabb {
+		Aabb::from_point3d(Point3d::from_coords(-1.0, -1.0, -1.0), Point3d::from_coords(1.0, 1.0, 1.0))
+	}
+	
+	fn ray(orig: (f32, f32, f32), dir: (f32, f32, f32)) -> Ray {
+		Ray::new(Point3d::from_coords(orig.0, orig.1, orig.2), Vector3d::from_coords(dir.0, dir.1, dir.2))
+	}
+	
+	#[test]
+	fn ray_from_outside() {
+		let t = unit_box().get_distance_to(&ray((0.5, 0.5, 5.0), (0.0, 0.0, -1.0))).unwrap();
+		assert!((t - 4.0).abs() < 1e-5);
+		let t = unit_box().get_distance_to(&ray((-3.0, -3.0, 0.0), (1.0, 1.0, 0.0))).unwrap();
+		assert!((t - 2.0).abs() < 1e-5);
+		assert!(unit_box().get_distance_to(&ray((3.0, 0.0, 0.0), (1.0, 0.1, 0.0))).is_none());
+		assert!(unit_box().get_distance_to(&ray((-3.0, 3.0, 0.0), (1.0, 0.1, 0.0))).is_none());
+	}
+	
+	#[test]
+	fn origin_inside_or_behind() {
+		// Inside the box the ray starts right away
+		assert_eq!(unit_box().get_distance_to(&ray((0.0, 0.0, 0.0), (0.3, -1.0, 0.2))), Some(0.0));
+		// The box is behind the origin
+		assert!(unit_box().get_distance_to(&ray((0.0, 0.0, 5.0), (0.0, 0.0, 1.0))).is_none());
+		assert!(unit_box().get_distance_to(&ray((4.0, 4.0, 4.0), (1.0, 1.0, 1.0))).is_none());
+	}
+	
+	#[test]
+	fn parallel_to_slabs() {
+		// Along the edge of the box, where the naive slab test produced NaN
+		let t = unit_box().get_distance_to(&ray((-1.0, 1.0, 5.0), (0.0, 0.0, -1.0))).unwrap();
+		assert!((t - 4.0).abs() < 1e-5);
+		let t = unit_box().get_distance_to(&ray((1.0, -5.0, 0.0), (0.0, 1.0, 0.0))).unwrap();
+		assert!((t - 4.0).abs() < 1e-5);
+		// Parallel, but outside of the slab
+		assert!(unit_box().get_distance_to(&ray((1.5, 0.0, 5.0), (0.0, 0.0, -1.0))).is_none());
+		assert!(unit_box().get_distance_to(&ray((0.0, -1.5, 5.0), (0.0, -0.0, -1.0))).is_none());
+	}
+	
+	#[test]
+	fn ray_interval() {
+		let mut r = ray((0.0, 0.0, 5.0), (0.0, 0.0, -1.0));
+		r.set_tmax(3.0);
+		assert!(unit_box().get_distance_to(&r).is_none());
+		let r = Ray::with_interval(Point3d::from_coords(0.0, 0.0, 5.0), Vector3d::from_coords(0.0, 0.0, -1.0), 5.5, 10.0);
+		assert_eq!(unit_box().get_distance_to(&r), Some(5.5));
+	}
+}
@@ -0,0 +1,93 @@
+use crate::geometry::{Point3d, Ray, Vector3d};
+use crate::geometry::TraceablePrimitive;
+use crate::geometry::aabb::Aabb;
+use crate::geometry::roots;
+
+/// Cone closed with a cap on the base. The apex is above the center of the base along +Y,
+/// other orientations are set by the transform of the SceneObj.
+#[derive(Copy, Clone)]
+pub struct Cone {
+    base: Point3d,
+    radius: f32,
+    height: f32,
+}
+
+impl Cone {
+    pub fn new(base: Point3d, radius: f32, height: f32) -> Cone {
+        Cone { base, radius, height }
+    }
+}
+
+impl TraceablePrimitive for Cone {
+    fn get_distance_to(&self, ray: &Ray) -> Option<f32> {
+        let o = ray.orig - self.base;
+        let d = ray.dir;
+        // x^2 + z^2 = (k * (height - y))^2
+        let k2 = (self.radius / self.height) * (self.radius / self.height);
+        let h = self.height - o.y;
+        let mut candidates = Vec::with_capacity(3);
+
+        let side = roots::solve_quadratic(
+            (d.x * d.x + d.z * d.z - k2 * d.y * d.y) as f64,
+            (2.0 * (o.x * d.x + o.z * d.z + k2 * h * d.y)) as f64,
+            (o.x * o.x + o.z * o.z - k2 * h * h) as f64,
+        );
+        // The other nappe of the double cone is above the apex
+        candidates.extend(side.into_iter()
+            .map(|t| t as f32)
+            .filter(|&t| (0.0..=self.height).contains(&(o.y + d.y * t))));
+
+        if d.y != 0.0 {
+            let t = -o.y / d.y;
+            let (x, z) = (o.x + d.x * t, o.z + d.z * t);
+            if x * x + z * z <= self.radius * self.radius {
+                candidates.push(t);
+            }
+        }
+        ray.get_closest(&candidates)
+    }
+
+    fn get_normal(&self, surface_pt: &Point3d) -> Vector3d {
+        const CAP_EPSILON: f32 = 1e-4;
+        let p = *surface_pt - self.base;
+        if p.y < CAP_EPSILON {
+            return Vector3d::from_coords(0.0, -1.0, 0.0);
+        }
+        let dist_to_axis = (p.x * p.x + p.z * p.z).sqrt();
+        if dist_to_axis == 0.0 {
+            // The apex
+            return Vector3d::from_coords(0.0, 1.0, 0.0);
+        }
+        Vector3d::from_coords(p.x, dist_to_axis * self.radius / self.height, p.z).normalize()
+    }
+
+    fn get_bounding_box(&self) -> Aabb {
+        Aabb::from_point3d(
+            self.base + Vector3d::from_coords(-self.radius, 0.0, -self.radius),
+            self.base + Vector3d::from_coords(self.radius, self.height, self.radius),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_and_base() {
+        // 45 degrees slope
+        let cone = Cone::new(Point3d::from_coords(0.0, 0.0, -10.0), 2.0, 2.0);
+        let ray = Ray::new(Point3d::from_coords(0.0, 1.0, 0.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+        let t = cone.get_distance_to(&ray).unwrap();
+        assert!((t - 9.0).abs() < 1e-5);
+        let n = cone.get_normal(&ray.get_point_at(t));
+        assert!((n.y - 0.5f32.sqrt()).abs() < 1e-5 && (n.z - 0.5f32.sqrt()).abs() < 1e-5);
+        let ray = Ray::new(Point3d::from_coords(1.5, -5.0, -10.0), Vector3d::from_coords(0.0, 1.0, 0.0));
+        let t = cone.get_distance_to(&ray).unwrap();
+        assert!((t - 5.0).abs() < 1e-5);
+        assert_eq!(cone.get_normal(&ray.get_point_at(t)).y, -1.0);
+        // Passes above the apex through the upper nappe of the double cone
+        let ray = Ray::new(Point3d::from_coords(0.0, 3.0, 0.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+        assert!(cone.get_distance_to(&ray).is_none());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::geometry::{Mat4f, Point3d, Quaternion, Ray, Vector3d};
+use crate::geometry::TraceablePrimitive;
+use crate::geometry::aabb::Aabb;
+
+/// Box with the given half sizes along its own axes, which are turned by the rotation
+#[derive(Copy, Clone)]
+pub struct Cuboid {
+    center: Point3d,
+    half_size: [f32; 3],
+    rotation: Quaternion,
+}
+
+impl Cuboid {
+    pub fn new(center: Point3d, half_size: [f32; 3], rotation: Quaternion) -> Cuboid {
+        Cuboid { center, half_size, rotation: rotation.normalize() }
+    }
+
+    /// The point or the direction in the frame of the box
+    fn get_box_coords(&self, v: &Vector3d) -> [f32; 3] {
+        let v = self.rotation.conjugate().rotate_vector(v);
+        [v.x, v.y, v.z]
+    }
+}
+
+impl TraceablePrimitive for Cuboid {
+    fn get_distance_to(&self, ray: &Ray) -> Option<f32> {
+        let orig = self.get_box_coords(&(ray.orig - self.center));
+        let dir = self.get_box_coords(&ray.dir);
+        let mut t_near = f32::NEG_INFINITY;
+        let mut t_far = f32::INFINITY;
+        for axis in 0..3 {
+            let h = self.half_size[axis];
+            if dir[axis] == 0.0 {
+                if orig[axis].abs() > h {
+                    return None;
+                }
+                continue;
+            }
+            let dir_inv = 1.0 / dir[axis];
+            let t0 = (-h - orig[axis]) * dir_inv;
+            let t1 = (h - orig[axis]) * dir_inv;
+            t_near = t_near.max(t0.min(t1));
+            t_far = t_far.min(t0.max(t1));
+            if t_near > t_far {
+                return None;
+            }
+        }
+        // From the inside the box is hit on the way out
+        ray.get_closest(&[t_near, t_far])
+    }
+
+    fn get_normal(&self, surface_pt: &Point3d) -> Vector3d {
+        let pt = self.get_box_coords(&(*surface_pt - self.center));
+        // The face the point is closest to, every point of a flat box is on its flat faces
+        let get_face_dist = |axis: usize| {
+            if self.half_size[axis] > 0.0 { pt[axis].abs() / self.half_size[axis] } else { f32::INFINITY }
+        };
+        let axis = (0..3)
+            .max_by(|&a, &b| get_face_dist(a).total_cmp(&get_face_dist(b)))
+            .unwrap();
+        let mut normal = [0.0; 3];
+        normal[axis] = pt[axis].signum();
+        self.rotation.rotate_vector(&Vector3d::from_array(&normal))
+    }
+
+    fn get_bounding_box(&self) -> Aabb {
+        let r = Mat4f::from(self.rotation).raw;
+        let h = self.half_size;
+        let extent = Vector3d::from_coords(
+            r[0][0].abs() * h[0] + r[0][1].abs() * h[1] + r[0][2].abs() * h[2],
+            r[1][0].abs() * h[0] + r[1][1].abs() * h[1] + r[1][2].abs() * h[2],
+            r[2][0].abs() * h[0] + r[2][1].abs() * h[1] + r[2][2].abs() * h[2],
+        );
+        Aabb::from_point3d(self.center + -extent, self.center + extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotated_box() {
+        // Turned by 45 degrees about Y, so the edge looks at +Z
+        let rotation = Quaternion::from_axis_angle(&Vector3d::from_coords(0.0, 1.0, 0.0), 45.0);
+        let cuboid = Cuboid::new(Point3d::from_coords(0.0, 0.0, -10.0), [1.0, 2.0, 1.0], rotation);
+        let ray = Ray::new(Point3d::from_coords(0.2, 0.0, 0.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+        let t = cuboid.get_distance_to(&ray).unwrap();
+        assert!((t - (10.0 - 2f32.sqrt() + 0.2)).abs() < 1e-5);
+        let n = cuboid.get_normal(&ray.get_point_at(t));
+        assert!((n.x - 0.5f32.sqrt()).abs() < 1e-5 && (n.z - 0.5f32.sqrt()).abs() < 1e-5);
+        // From the inside the far face is hit
+        let ray = Ray::new(Point3d::from_coords(0.0, 0.0, -10.0), Vector3d::from_coords(0.0, 1.0, 0.0));
+        assert!((cuboid.get_distance_to(&ray).unwrap() - 2.0).abs() < 1e-5);
+        let b_box = cuboid.get_bounding_box();
+        assert!((b_box.get_max().x - 2f32.sqrt()).abs() < 1e-5 && (b_box.get_max().y - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn flat_box() {
+        let cuboid = Cuboid::new(Point3d::new(), [1.0, 0.0, 1.0], Quaternion::identity());
+        let ray = Ray::new(Point3d::from_coords(0.5, 5.0, 0.2), Vector3d::from_coords(0.0, -1.0, 0.0));
+        let t = cuboid.get_distance_to(&ray).unwrap();
+        assert!((t - 5.0).abs() < 1e-5);
+        let n = cuboid.get_normal(&ray.get_point_at(t));
+        assert!((n.y.abs() - 1.0).abs() < 1e-5, "{:?}", (n.x, n.y, n.z));
+        // Even at the center, where every coordinate is 0
+        assert!((cuboid.get_normal(&Point3d::new()).y.abs() - 1.0).abs() < 1e-5);
+    }
+}
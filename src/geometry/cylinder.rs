@@ -0,0 +1,90 @@
+use crate::geometry::{Point3d, Ray, Vector3d};
+use crate::geometry::TraceablePrimitive;
+use crate::geometry::aabb::Aabb;
+use crate::geometry::roots;
+
+/// Cylinder closed with caps on both ends. It stands on the base along +Y,
+/// other orientations are set by the transform of the SceneObj.
+#[derive(Copy, Clone)]
+pub struct Cylinder {
+    base: Point3d,
+    radius: f32,
+    height: f32,
+}
+
+impl Cylinder {
+    pub fn new(base: Point3d, radius: f32, height: f32) -> Cylinder {
+        Cylinder { base, radius, height }
+    }
+}
+
+impl TraceablePrimitive for Cylinder {
+    fn get_distance_to(&self, ray: &Ray) -> Option<f32> {
+        let o = ray.orig - self.base;
+        let d = ray.dir;
+        let r2 = self.radius * self.radius;
+        let mut candidates = Vec::with_capacity(4);
+
+        let side = roots::solve_quadratic(
+            (d.x * d.x + d.z * d.z) as f64,
+            (2.0 * (o.x * d.x + o.z * d.z)) as f64,
+            (o.x * o.x + o.z * o.z - r2) as f64,
+        );
+        candidates.extend(side.into_iter()
+            .map(|t| t as f32)
+            .filter(|&t| (0.0..=self.height).contains(&(o.y + d.y * t))));
+
+        if d.y != 0.0 {
+            for &cap_y in &[0.0, self.height] {
+                let t = (cap_y - o.y) / d.y;
+                let (x, z) = (o.x + d.x * t, o.z + d.z * t);
+                if x * x + z * z <= r2 {
+                    candidates.push(t);
+                }
+            }
+        }
+        ray.get_closest(&candidates)
+    }
+
+    fn get_normal(&self, surface_pt: &Point3d) -> Vector3d {
+        const CAP_EPSILON: f32 = 1e-4;
+        let p = *surface_pt - self.base;
+        if p.y < CAP_EPSILON {
+            Vector3d::from_coords(0.0, -1.0, 0.0)
+        } else if p.y > self.height - CAP_EPSILON {
+            Vector3d::from_coords(0.0, 1.0, 0.0)
+        } else {
+            Vector3d::from_coords(p.x, 0.0, p.z).normalize()
+        }
+    }
+
+    fn get_bounding_box(&self) -> Aabb {
+        Aabb::from_point3d(
+            self.base + Vector3d::from_coords(-self.radius, 0.0, -self.radius),
+            self.base + Vector3d::from_coords(self.radius, self.height, self.radius),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_and_caps() {
+        let cylinder = Cylinder::new(Point3d::from_coords(0.0, -1.0, -10.0), 2.0, 3.0);
+        let ray = Ray::new(Point3d::from_coords(1.0, 0.0, 0.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+        let t = cylinder.get_distance_to(&ray).unwrap();
+        assert!((t - (10.0 - 3f32.sqrt())).abs() < 1e-5);
+        let n = cylinder.get_normal(&ray.get_point_at(t));
+        assert!((n.x - 0.5).abs() < 1e-5 && n.y == 0.0);
+        // Looking down on the top cap
+        let ray = Ray::new(Point3d::from_coords(0.5, 10.0, -10.0), Vector3d::from_coords(0.0, -1.0, 0.0));
+        let t = cylinder.get_distance_to(&ray).unwrap();
+        assert!((t - 8.0).abs() < 1e-5);
+        assert_eq!(cylinder.get_normal(&ray.get_point_at(t)).y, 1.0);
+        // Above the top cap
+        let ray = Ray::new(Point3d::from_coords(0.0, 2.5, 0.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+        assert!(cylinder.get_distance_to(&ray).is_none());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::geometry::{Point3d, Ray, Vector3d};
+use crate::geometry::TraceablePrimitive;
+use crate::geometry::aabb::Aabb;
+
+#[derive(Copy, Clone)]
+pub struct Disk {
+    center: Point3d,
+    normal: Vector3d,
+    radius: f32,
+}
+
+impl Disk {
+    pub fn new(center: Point3d, normal: Vector3d, radius: f32) -> Disk {
+        Disk { center, normal: normal.normalize(), radius }
+    }
+}
+
+impl TraceablePrimitive for Disk {
+    fn get_distance_to(&self, ray: &Ray) -> Option<f32> {
+        let denom = self.normal * ray.dir;
+        if denom == 0.0 {
+            return None;
+        }
+        let t = (self.center - ray.orig) * self.normal / denom;
+        let from_center = ray.get_point_at(t) - self.center;
+        if from_center * from_center > self.radius * self.radius {
+            return None;
+        }
+        ray.get_closest(&[t])
+    }
+
+    fn get_normal(&self, _: &Point3d) -> Vector3d {
+        self.normal
+    }
+
+    fn get_bounding_box(&self) -> Aabb {
+        // How far the rim reaches along every axis
+        let n = self.normal;
+        let extent = Vector3d::from_coords(
+            self.radius * (1.0 - n.x * n.x).max(0.0).sqrt(),
+            self.radius * (1.0 - n.y * n.y).max(0.0).sqrt(),
+            self.radius * (1.0 - n.z * n.z).max(0.0).sqrt(),
+        );
+        Aabb::from_point3d(self.center + -extent, self.center + extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tilted_disk() {
+        let disk = Disk::new(Point3d::from_coords(0.0, 0.0, -5.0), Vector3d::from_coords(1.0, 0.0, 1.0), 2.0);
+        let ray = Ray::new(Point3d::new(), Vector3d::from_coords(0.0, 0.0, -1.0));
+        assert!((disk.get_distance_to(&ray).unwrap() - 5.0).abs() < 1e-5);
+        // 1.5 off the center along the plane is still inside, 2.5 is not
+        let ray = Ray::new(Point3d::from_coords(0.0, 1.5, 0.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+        assert!(disk.get_distance_to(&ray).is_some());
+        let ray = Ray::new(Point3d::from_coords(0.0, 2.5, 0.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+        assert!(disk.get_distance_to(&ray).is_none());
+        let b_box = disk.get_bounding_box();
+        assert!((b_box.get_max().y - 2.0).abs() < 1e-5 && (b_box.get_max().x - 2f32.sqrt()).abs() < 1e-5);
+    }
+}
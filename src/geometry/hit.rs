@@ -0,0 +1,30 @@
+use crate::geometry::{Point3d, Vector3d};
+
+/// Everything the shading needs to know about the closest intersection of a ray
+#[derive(Copy, Clone)]
+pub struct Hit {
+    pub distance: f32,
+    pub point: Point3d,
+    /// Normal of the surface itself, not interpolated from the vertices
+    pub normal: Vector3d,
+    /// Weights of the triangle vertices at the hit point, for interpolating vertex attributes.
+    /// Primitives without vertices leave them as (1, 0, 0).
+    pub barycentric: [f32; 3],
+    /// Index of the primitive within the Mesh
+    pub prim_idx: usize,
+    /// Index of the owning SceneObj in the Scene::flatten() order
+    pub obj_idx: usize,
+}
+
+impl Hit {
+    pub fn new(distance: f32, point: Point3d, normal: Vector3d) -> Self {
+        Hit {
+            distance,
+            point,
+            normal,
+            barycentric: [1.0, 0.0, 0.0],
+            prim_idx: 0,
+            obj_idx: 0,
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use crate::geometry::{Hit, Mat4f, Point3d, Point4d, Ray, TraceablePrimitive, Vector3d};
+use crate::geometry::aabb::Aabb;
+
+/// A primitive defined in its model space and placed into the world by a matrix.
+/// Rays are transformed into the model space instead of the primitive, so e.g. a sphere
+/// scaled non-uniformly becomes an exact ellipsoid.
+#[derive(Clone)]
+pub struct Instance {
+    prim: Arc<dyn TraceablePrimitive>,
+    world_to_model: Mat4f,
+    normal_mtx: Mat4f,
+    b_box: Aabb,
+}
+
+impl Instance {
+    pub fn new(prim: Arc<dyn TraceablePrimitive>, model_to_world: Mat4f) -> Self {
+        let b_box = transform_bounding_box(&prim.get_bounding_box(), &model_to_world);
+        Instance {
+            prim,
+            // A zero scale collapses the primitive, the box above is already degenerate then
+            world_to_model: model_to_world.inverse().unwrap_or_else(Mat4f::identity),
+            normal_mtx: model_to_world.get_normal_mtx(),
+            b_box,
+        }
+    }
+
+    /// The ray in the model space with the normalized direction, and the ratio
+    /// of the model-space distances to the world-space ones
+    fn to_model_space(&self, ray: &Ray) -> (Ray, f32) {
+        let orig = Point3d::from(&self.world_to_model * Point4d::from(ray.orig));
+        let dir = &self.world_to_model * ray.dir;
+        let scale = (dir * dir).sqrt();
+        let tmax = if ray.tmax == f32::MAX { f32::MAX } else { ray.tmax * scale };
+        (Ray::with_interval(orig, dir * (1.0 / scale), ray.tmin * scale, tmax), scale)
+    }
+}
+
+impl TraceablePrimitive for Instance {
+    fn get_distance_to(&self, ray: &Ray) -> Option<f32> {
+        let (model_ray, scale) = self.to_model_space(ray);
+        Some(self.prim.get_distance_to(&model_ray)? / scale)
+    }
+
+    fn get_hit(&self, ray: &Ray) -> Option<Hit> {
+        let (model_ray, scale) = self.to_model_space(ray);
+        let hit = self.prim.get_hit(&model_ray)?;
+        let distance = hit.distance / scale;
+        Some(Hit {
+            distance,
+            point: ray.get_point_at(distance),
+            normal: (&self.normal_mtx * hit.normal).normalize(),
+            ..hit
+        })
+    }
+
+    fn get_normal(&self, surface_pt: &Point3d) -> Vector3d {
+        let model_pt = Point3d::from(&self.world_to_model * Point4d::from(*surface_pt));
+        (&self.normal_mtx * self.prim.get_normal(&model_pt)).normalize()
+    }
+
+    fn get_bounding_box(&self) -> Aabb {
+        self.b_box
+    }
+}
+
+/// The box around all 8 transformed corners of the box
+fn transform_bounding_box(b_box: &Aabb, mtx: &Mat4f) -> Aabb {
+    if !b_box.is_finite() {
+        return Aabb::infinite();
+    }
+    let bounds = [b_box.get_min(), b_box.get_max()];
+    (0..8).fold(Aabb::new(), |res, corner| {
+        let pt = Point3d::from_coords(
+            bounds[corner & 1].x,
+            bounds[(corner >> 1) & 1].y,
+            bounds[(corner >> 2) & 1].z,
+        );
+        let pt = Point3d::from(mtx * Point4d::from(pt));
+        res.get_superset(Aabb::from_point3d(pt, pt))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::sphere::Sphere;
+
+    #[test]
+    fn scaled_sphere() {
+        // An ellipsoid with the semi-axes 2, 1, 1 at (0, 0, -10)
+        let sphere = Arc::new(Sphere::new(Point3d::new(), 1.0));
+        let mtx = Mat4f::identity().translate_xyz(&[0.0, 0.0, -10.0]).scale_xyz(&[2.0, 1.0, 1.0]);
+        let ellipsoid = Instance::new(sphere, mtx);
+        let b_box = ellipsoid.get_bounding_box();
+        assert!((b_box.get_min().x + 2.0).abs() < 1e-5 && (b_box.get_max().z + 9.0).abs() < 1e-5);
+
+        let ray = Ray::new(Point3d::from_coords(5.0, 0.0, -10.0), Vector3d::from_coords(-1.0, 0.0, 0.0));
+        let hit = ellipsoid.get_hit(&ray).unwrap();
+        assert!((hit.distance - 3.0).abs() < 1e-5);
+        assert!((hit.point.x - 2.0).abs() < 1e-5);
+        assert!((hit.normal.x - 1.0).abs() < 1e-5);
+
+        let mut ray = Ray::new(Point3d::from_coords(1.0, 0.0, 0.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+        let hit = ellipsoid.get_hit(&ray).unwrap();
+        assert!((hit.point.z + 10.0 - 0.75f32.sqrt()).abs() < 1e-5);
+        // Along the gradient of x^2 / 4 + y^2 + (z + 10)^2, i.e. (x / 4, y, z + 10)
+        let expected = Vector3d::from_coords(0.25, 0.0, 0.75f32.sqrt()).normalize();
+        assert!((hit.normal.x - expected.x).abs() < 1e-5 && (hit.normal.z - expected.z).abs() < 1e-5);
+        ray.set_tmax(5.0);
+        assert!(ellipsoid.get_distance_to(&ray).is_none());
+    }
+}
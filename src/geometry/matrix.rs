@@ -1,5 +1,6 @@
-use crate::geometry::Point4d;
+use crate::geometry::{Point4d, Quaternion, Vector3d};
 
+#[derive(Copy, Clone, Default)]
 pub struct Mat4f {
     pub raw: [[f32; 4]; 4],
 }
@@ -22,6 +23,37 @@ impl Mat4f {
         Mat4f {
             raw: [a, b, c, d],
         }
+    }
+    /// Perspective projection for a camera looking down -Z, maps the view frustum to [-1; 1] NDC
+    pub fn perspective(fov_vert: f32, aspect_ratio: f32, z_near: f32, z_far: f32) -> Self {
+        let f = 1.0 / (fov_vert / 2.0).to_radians().tan();
+        let depth = z_near - z_far;
+        Mat4f::from_rows(
+            [f / aspect_ratio, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, (z_far + z_near) / depth, 2.0 * z_far * z_near / depth],
+            [0.0, 0.0, -1.0, 0.0],
+        )
+    }
+    /// Orthographic projection for a camera looking down -Z, the view volume is height units tall
+    pub fn orthographic(height: f32, aspect_ratio: f32, z_near: f32, z_far: f32) -> Self {
+        let width = height * aspect_ratio;
+        let depth = z_near - z_far;
+        Mat4f::from_rows(
+            [2.0 / width, 0.0, 0.0, 0.0],
+            [0.0, 2.0 / height, 0.0, 0.0],
+            [0.0, 0.0, 2.0 / depth, (z_far + z_near) / depth],
+            [0.0, 0.0, 0.0, 1.0],
+        )
+    }
+    /// Maps NDC X and Y to [0; width] and [0; height] screen coordinates, and Z to [0; 1] depth
+    pub fn viewport(width: f32, height: f32) -> Self {
+        Mat4f::from_rows(
+            [width / 2.0, 0.0, 0.0, width / 2.0],
+            [0.0, height / 2.0, 0.0, height / 2.0],
+            [0.0, 0.0, 0.5, 0.5],
+            [0.0, 0.0, 0.0, 1.0],
+        )
     }
 	pub fn rotate_about_x(&self, angle_deg: f32) -> Self {
 		let sin = angle_deg.to_radians().sin();
@@ -66,6 +98,10 @@ impl Mat4f {
 		self * &rz
 	}
 	
+	pub fn rotate(&self, q: &Quaternion) -> Self {
+		self * &Mat4f::from(*q)
+	}
+	
 	pub fn translate_xyz(&self, translation: &[f32]) -> Self {
 		let t = Mat4f {
 			raw: [
@@ -90,9 +126,73 @@ impl Mat4f {
 		self * &s
 	}
 	
+	pub fn transpose(&self) -> Self {
+		let mut m = Mat4f::new();
+		for i in 0..4 {
+			for j in 0..4 {
+				m.raw[i][j] = self.raw[j][i];
+			}
+		}
+		m
+	}
+	
+	/// Laplace expansion along the first row
+	pub fn determinant(&self) -> f32 {
+		let r = &self.raw;
+		let minor = |col: usize| {
+			let c: Vec<usize> = (0..4).filter(|&j| j != col).collect();
+			r[1][c[0]] * (r[2][c[1]] * r[3][c[2]] - r[2][c[2]] * r[3][c[1]])
+				- r[1][c[1]] * (r[2][c[0]] * r[3][c[2]] - r[2][c[2]] * r[3][c[0]])
+				+ r[1][c[2]] * (r[2][c[0]] * r[3][c[1]] - r[2][c[1]] * r[3][c[0]])
+		};
+		r[0][0] * minor(0) - r[0][1] * minor(1) + r[0][2] * minor(2) - r[0][3] * minor(3)
+	}
+	
+	/// Gauss-Jordan elimination with partial pivoting, returns None if the matrix is singular
+	pub fn inverse(&self) -> Option<Self> {
+		let mut a = self.raw;
+		let mut inv = Mat4f::identity().raw;
+		for col in 0..4 {
+			let mut pivot = col;
+			for i in col + 1..4 {
+				if a[i][col].abs() > a[pivot][col].abs() {
+					pivot = i;
+				}
+			}
+			if a[pivot][col] == 0.0 {
+				return None;
+			}
+			a.swap(col, pivot);
+			inv.swap(col, pivot);
+			let pivot_inv = 1.0 / a[col][col];
+			for j in 0..4 {
+				a[col][j] *= pivot_inv;
+				inv[col][j] *= pivot_inv;
+			}
+			for i in 0..4 {
+				if i != col {
+					let factor = a[i][col];
+					for j in 0..4 {
+						a[i][j] -= factor * a[col][j];
+						inv[i][j] -= factor * inv[col][j];
+					}
+				}
+			}
+		}
+		Some(Mat4f { raw: inv })
+	}
+	
+	/// Inverse transpose of the matrix, which keeps normals perpendicular to the surface
+	/// under non-uniform scaling. Falls back to the matrix itself if it is singular.
+	pub fn get_normal_mtx(&self) -> Self {
+		match self.inverse() {
+			Some(inv) => inv.transpose(),
+			None => *self,
+		}
+	}
 }
 
-impl<'a, 'b> core::ops::Mul<&'b Mat4f> for &'a Mat4f {
+impl<'b> core::ops::Mul<&'b Mat4f> for &Mat4f {
     type Output = Mat4f;
 
     fn mul(self, other: &'b Mat4f) -> Self::Output {
@@ -109,7 +209,7 @@ impl<'a, 'b> core::ops::Mul<&'b Mat4f> for &'a Mat4f {
     }
 }
 
-impl<'a> core::ops::Mul<Point4d> for &'a Mat4f {
+impl core::ops::Mul<Point4d> for &Mat4f {
     type Output = Point4d;
 
     fn mul(self, other: Point4d) -> Self::Output {
@@ -124,3 +224,119 @@ impl<'a> core::ops::Mul<Point4d> for &'a Mat4f {
     }
 }
 
+
+/// Transforms a direction, i.e. ignores the translation. Use get_normal_mtx() for normals.
+impl core::ops::Mul<Vector3d> for &Mat4f {
+    type Output = Vector3d;
+
+    fn mul(self, other: Vector3d) -> Self::Output {
+        let r = &self.raw;
+        Vector3d::from_coords(
+            r[0][0] * other.x + r[0][1] * other.y + r[0][2] * other.z,
+            r[1][0] * other.x + r[1][1] * other.y + r[1][2] * other.z,
+            r[2][0] * other.x + r[2][1] * other.y + r[2][2] * other.z,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_mtx_near(a: &Mat4f, b: &Mat4f) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a.raw[i][j] - b.raw[i][j]).abs() < 1e-5, "[{}][{}]: {} != {}", i, j, a.raw[i][j], b.raw[i][j]);
+            }
+        }
+    }
+
+    #[test]
+    fn transpose() {
+        let m = Mat4f::from_rows(
+            [1.0, 2.0, 3.0, 4.0],
+            [5.0, 6.0, 7.0, 8.0],
+            [9.0, 10.0, 11.0, 12.0],
+            [13.0, 14.0, 15.0, 16.0],
+        );
+        let t = Mat4f::from_rows(
+            [1.0, 5.0, 9.0, 13.0],
+            [2.0, 6.0, 10.0, 14.0],
+            [3.0, 7.0, 11.0, 15.0],
+            [4.0, 8.0, 12.0, 16.0],
+        );
+        assert_mtx_near(&m.transpose(), &t);
+        assert_mtx_near(&t.transpose(), &m);
+    }
+
+    #[test]
+    fn determinant() {
+        assert_eq!(Mat4f::identity().determinant(), 1.0);
+        let m = Mat4f::from_rows(
+            [1.0, 1.0, 1.0, -1.0],
+            [1.0, 1.0, -1.0, 1.0],
+            [1.0, -1.0, 1.0, 1.0],
+            [-1.0, 1.0, 1.0, 1.0],
+        );
+        assert_eq!(m.determinant(), -16.0);
+        let m = Mat4f::identity().scale_xyz(&[2.0, 3.0, 4.0]).translate_xyz(&[5.0, 6.0, 7.0]);
+        assert_eq!(m.determinant(), 24.0);
+        let m = Mat4f::from_rows(
+            [1.0, 2.0, 3.0, 4.0],
+            [5.0, 6.0, 7.0, 8.0],
+            [9.0, 10.0, 11.0, 12.0],
+            [13.0, 14.0, 15.0, 16.0],
+        );
+        assert_eq!(m.determinant(), 0.0);
+    }
+
+    #[test]
+    fn inverse() {
+        let m = Mat4f::from_rows(
+            [1.0, 1.0, 1.0, -1.0],
+            [1.0, 1.0, -1.0, 1.0],
+            [1.0, -1.0, 1.0, 1.0],
+            [-1.0, 1.0, 1.0, 1.0],
+        );
+        let expected = Mat4f::from_rows(
+            [0.25, 0.25, 0.25, -0.25],
+            [0.25, 0.25, -0.25, 0.25],
+            [0.25, -0.25, 0.25, 0.25],
+            [-0.25, 0.25, 0.25, 0.25],
+        );
+        assert_mtx_near(&m.inverse().unwrap(), &expected);
+
+        let m = Mat4f::identity()
+            .translate_xyz(&[3.0, -2.0, 10.0])
+            .rotate_about_x(30.0)
+            .rotate_about_y(-45.0)
+            .scale_xyz(&[2.0, 0.5, 7.0]);
+        assert_mtx_near(&(&m * &m.inverse().unwrap()), &Mat4f::identity());
+        assert_mtx_near(&(&m.inverse().unwrap() * &m), &Mat4f::identity());
+
+        let m = Mat4f::identity().translate_xyz(&[1.0, 2.0, 3.0]);
+        assert_mtx_near(&m.inverse().unwrap(), &Mat4f::identity().translate_xyz(&[-1.0, -2.0, -3.0]));
+
+        assert!(Mat4f::identity().scale_xyz(&[1.0, 0.0, 1.0]).inverse().is_none());
+    }
+
+    #[test]
+    fn transform_direction() {
+        let m = Mat4f::identity().translate_xyz(&[10.0, 20.0, 30.0]).rotate_about_z(90.0);
+        let v = &m * Vector3d::from_coords(1.0, 0.0, 0.0);
+        assert!(v.x.abs() < 1e-6 && (v.y - 1.0).abs() < 1e-6 && v.z.abs() < 1e-6);
+    }
+
+    #[test]
+    fn transform_normal_under_non_uniform_scale() {
+        let m = Mat4f::identity().scale_xyz(&[2.0, 1.0, 1.0]);
+        // The surface x + y = 0
+        let normal = Vector3d::from_coords(1.0, 1.0, 0.0).normalize();
+        let tangent = Vector3d::from_coords(1.0, -1.0, 0.0);
+        let normal = (&m.get_normal_mtx() * normal).normalize();
+        let tangent = &m * tangent;
+        assert!((normal * tangent).abs() < 1e-6);
+        let expected = Vector3d::from_coords(0.5, 1.0, 0.0).normalize();
+        assert!((normal.x - expected.x).abs() < 1e-6 && (normal.y - expected.y).abs() < 1e-6);
+    }
+}
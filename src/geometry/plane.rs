@@ -0,0 +1,54 @@
+use crate::geometry::{Point3d, Ray, Vector3d};
+use crate::geometry::TraceablePrimitive;
+use crate::geometry::aabb::Aabb;
+
+/// Infinite plane going through the point, e.g. the ground
+#[derive(Copy, Clone)]
+pub struct Plane {
+    point: Point3d,
+    normal: Vector3d,
+}
+
+impl Plane {
+    pub fn new(point: Point3d, normal: Vector3d) -> Plane {
+        Plane { point, normal: normal.normalize() }
+    }
+}
+
+impl TraceablePrimitive for Plane {
+    fn get_distance_to(&self, ray: &Ray) -> Option<f32> {
+        let denom = self.normal * ray.dir;
+        if denom == 0.0 {
+            // The ray is parallel to the plane
+            return None;
+        }
+        let t = (self.point - ray.orig) * self.normal / denom;
+        ray.get_closest(&[t])
+    }
+
+    fn get_normal(&self, _: &Point3d) -> Vector3d {
+        self.normal
+    }
+
+    /// Unbounded, so the Mesh tests the planes against every ray instead of keeping them in the BVH
+    fn get_bounding_box(&self) -> Aabb {
+        Aabb::infinite()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ground_plane() {
+        let plane = Plane::new(Point3d::from_coords(0.0, -1.0, 0.0), Vector3d::from_coords(0.0, 2.0, 0.0));
+        let ray = Ray::new(Point3d::from_coords(3.0, 1.0, 0.0), Vector3d::from_coords(0.0, -1.0, -1.0).normalize());
+        let t = plane.get_distance_to(&ray).unwrap();
+        assert!((ray.get_point_at(t).y + 1.0).abs() < 1e-5);
+        assert_eq!(plane.get_normal(&ray.get_point_at(t)).y, 1.0);
+        assert!(plane.get_distance_to(&Ray::new(Point3d::new(), Vector3d::from_coords(1.0, 0.0, 0.0))).is_none());
+        assert!(plane.get_distance_to(&Ray::new(Point3d::new(), Vector3d::from_coords(0.0, 1.0, 0.0))).is_none());
+        assert!(!plane.get_bounding_box().is_finite());
+    }
+}
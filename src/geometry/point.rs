@@ -11,7 +11,7 @@ use crate::geometry::Vector3d;
 // 	}
 // }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 pub struct Point3d {
 	pub(crate) x: f32,
 	pub(crate) y: f32,
@@ -189,7 +189,7 @@ impl IntoIterator for Point3d {
 }
 */
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 pub struct Point4d {
 	pub(crate) x: f32,
 	pub(crate) y: f32,
@@ -0,0 +1,257 @@
+use crate::geometry::{Mat4f, Vector3d};
+
+/// Rotation quaternion, w is the scalar part
+#[derive(Copy, Clone, Debug)]
+pub struct Quaternion {
+    pub(crate) w: f32,
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) z: f32,
+}
+
+impl Quaternion {
+    pub fn identity() -> Self {
+        Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 }
+    }
+    pub fn from_coords(w: f32, x: f32, y: f32, z: f32) -> Self {
+        Quaternion { w, x, y, z }
+    }
+
+    /// Counterclockwise rotation about the axis when looking from its end towards the origin
+    pub fn from_axis_angle(axis: &Vector3d, angle_deg: f32) -> Self {
+        let axis = axis.normalize();
+        let half_angle = angle_deg.to_radians() * 0.5;
+        let sin = half_angle.sin();
+        Quaternion {
+            w: half_angle.cos(),
+            x: axis.x * sin,
+            y: axis.y * sin,
+            z: axis.z * sin,
+        }
+    }
+
+    /// Same as Mat4f::identity().rotate_about_x(x).rotate_about_y(y).rotate_about_z(z)
+    pub fn from_euler(x_deg: f32, y_deg: f32, z_deg: f32) -> Self {
+        Quaternion::from_axis_angle(&Vector3d::from_coords(1.0, 0.0, 0.0), x_deg)
+            * Quaternion::from_axis_angle(&Vector3d::from_coords(0.0, 1.0, 0.0), y_deg)
+            * Quaternion::from_axis_angle(&Vector3d::from_coords(0.0, 0.0, 1.0), z_deg)
+    }
+
+    /// Rotation which turns -Z towards forward and +Y as close to up as possible,
+    /// the same convention the camera uses
+    pub fn look_rotation(forward: &Vector3d, up: &Vector3d) -> Self {
+        let backward = -forward.normalize();
+        let right = up.crossprod(&backward).normalize();
+        let up = backward.crossprod(&right);
+        Quaternion::from(&Mat4f::from_rows(
+            [right.x, up.x, backward.x, 0.0],
+            [right.y, up.y, backward.y, 0.0],
+            [right.z, up.z, backward.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ))
+    }
+
+    pub fn get_length(&self) -> f32 {
+        (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let length_inverted = 1.0 / self.get_length();
+        Quaternion {
+            w: self.w * length_inverted,
+            x: self.x * length_inverted,
+            y: self.y * length_inverted,
+            z: self.z * length_inverted,
+        }
+    }
+
+    /// For a unit quaternion this is the inverse rotation
+    pub fn conjugate(&self) -> Self {
+        Quaternion { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    pub fn dot(&self, other: &Self) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn rotate_vector(&self, v: &Vector3d) -> Vector3d {
+        let q = Vector3d::from_coords(self.x, self.y, self.z);
+        let t = q.crossprod(v) * 2.0;
+        *v + t * self.w + q.crossprod(&t)
+    }
+
+    /// Spherical linear interpolation along the shortest arc, t is in [0; 1]
+    pub fn slerp(&self, other: &Self, t: f32) -> Self {
+        let mut other = *other;
+        let mut cos = self.dot(&other);
+        if cos < 0.0 {
+            other = -other;
+            cos = -cos;
+        }
+        let (k0, k1) = if cos > 0.9995 {
+            // The angle is too small for sin() in the denominator, fall back to the linear interpolation
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin_inv = 1.0 / angle.sin();
+            (((1.0 - t) * angle).sin() * sin_inv, (t * angle).sin() * sin_inv)
+        };
+        Quaternion {
+            w: self.w * k0 + other.w * k1,
+            x: self.x * k0 + other.x * k1,
+            y: self.y * k0 + other.y * k1,
+            z: self.z * k0 + other.z * k1,
+        }.normalize()
+    }
+}
+
+impl core::ops::Mul<Quaternion> for Quaternion {
+    type Output = Self;
+
+    /// Hamilton product, the result rotates by other first and then by self
+    fn mul(self, other: Self) -> Self::Output {
+        Quaternion {
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        }
+    }
+}
+
+impl core::ops::Neg for Quaternion {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Quaternion { w: -self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+}
+
+impl core::convert::From<Quaternion> for Mat4f {
+    fn from(q: Quaternion) -> Mat4f {
+        let q = q.normalize();
+        let (w, x, y, z) = (q.w, q.x, q.y, q.z);
+        Mat4f::from_rows(
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        )
+    }
+}
+
+/// Extracts the rotation from the upper 3x3 part of the matrix, which must not contain scaling
+impl core::convert::From<&Mat4f> for Quaternion {
+    fn from(m: &Mat4f) -> Quaternion {
+        let r = &m.raw;
+        let trace = r[0][0] + r[1][1] + r[2][2];
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quaternion {
+                w: 0.25 * s,
+                x: (r[2][1] - r[1][2]) / s,
+                y: (r[0][2] - r[2][0]) / s,
+                z: (r[1][0] - r[0][1]) / s,
+            }
+        } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+            let s = (1.0 + r[0][0] - r[1][1] - r[2][2]).sqrt() * 2.0;
+            Quaternion {
+                w: (r[2][1] - r[1][2]) / s,
+                x: 0.25 * s,
+                y: (r[0][1] + r[1][0]) / s,
+                z: (r[0][2] + r[2][0]) / s,
+            }
+        } else if r[1][1] > r[2][2] {
+            let s = (1.0 + r[1][1] - r[0][0] - r[2][2]).sqrt() * 2.0;
+            Quaternion {
+                w: (r[0][2] - r[2][0]) / s,
+                x: (r[0][1] + r[1][0]) / s,
+                y: 0.25 * s,
+                z: (r[1][2] + r[2][1]) / s,
+            }
+        } else {
+            let s = (1.0 + r[2][2] - r[0][0] - r[1][1]).sqrt() * 2.0;
+            Quaternion {
+                w: (r[1][0] - r[0][1]) / s,
+                x: (r[0][2] + r[2][0]) / s,
+                y: (r[1][2] + r[2][1]) / s,
+                z: 0.25 * s,
+            }
+        };
+        q.normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_mtx_near(a: &Mat4f, b: &Mat4f) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a.raw[i][j] - b.raw[i][j]).abs() < 1e-5, "[{}][{}]: {} != {}", i, j, a.raw[i][j], b.raw[i][j]);
+            }
+        }
+    }
+
+    fn assert_vec_near(a: &Vector3d, b: &Vector3d) {
+        assert!((a.x - b.x).abs() < 1e-5 && (a.y - b.y).abs() < 1e-5 && (a.z - b.z).abs() < 1e-5,
+            "({}, {}, {}) != ({}, {}, {})", a.x, a.y, a.z, b.x, b.y, b.z);
+    }
+
+    #[test]
+    fn axis_angle_matches_matrices() {
+        let q = Quaternion::from_axis_angle(&Vector3d::from_coords(1.0, 0.0, 0.0), 30.0);
+        assert_mtx_near(&Mat4f::from(q), &Mat4f::identity().rotate_about_x(30.0));
+        let q = Quaternion::from_axis_angle(&Vector3d::from_coords(0.0, 2.0, 0.0), -70.0);
+        assert_mtx_near(&Mat4f::from(q), &Mat4f::identity().rotate_about_y(-70.0));
+        let q = Quaternion::from_axis_angle(&Vector3d::from_coords(0.0, 0.0, 1.0), 135.0);
+        assert_mtx_near(&Mat4f::from(q), &Mat4f::identity().rotate_about_z(135.0));
+    }
+
+    #[test]
+    fn euler_matches_matrices() {
+        let q = Quaternion::from_euler(10.0, 80.0, -45.0);
+        let m = Mat4f::identity().rotate_about_x(10.0).rotate_about_y(80.0).rotate_about_z(-45.0);
+        assert_mtx_near(&Mat4f::from(q), &m);
+    }
+
+    #[test]
+    fn arbitrary_axis() {
+        // 120 degrees about the main diagonal cycles the axes
+        let q = Quaternion::from_axis_angle(&Vector3d::from_coords(1.0, 1.0, 1.0), 120.0);
+        assert_vec_near(&q.rotate_vector(&Vector3d::from_coords(1.0, 0.0, 0.0)), &Vector3d::from_coords(0.0, 1.0, 0.0));
+        assert_vec_near(&q.rotate_vector(&Vector3d::from_coords(0.0, 0.0, 1.0)), &Vector3d::from_coords(1.0, 0.0, 0.0));
+        let v = Vector3d::from_coords(0.3, -2.0, 5.0);
+        assert_vec_near(&q.rotate_vector(&v), &(&Mat4f::from(q) * v));
+    }
+
+    #[test]
+    fn matrix_roundtrip() {
+        for &(x, y, z) in &[(0.0, 0.0, 0.0), (10.0, 20.0, 30.0), (180.0, 0.0, 0.0), (0.0, 179.0, 90.0), (-90.0, 45.0, 170.0)] {
+            let m = Mat4f::identity().rotate_about_x(x).rotate_about_y(y).rotate_about_z(z);
+            assert_mtx_near(&Mat4f::from(Quaternion::from(&m)), &m);
+        }
+    }
+
+    #[test]
+    fn slerp() {
+        let axis = Vector3d::from_coords(0.0, 1.0, 0.0);
+        let a = Quaternion::from_axis_angle(&axis, 10.0);
+        let b = Quaternion::from_axis_angle(&axis, 90.0);
+        let q = a.slerp(&b, 0.25);
+        assert_mtx_near(&Mat4f::from(q), &Mat4f::from(Quaternion::from_axis_angle(&axis, 30.0)));
+        assert_mtx_near(&Mat4f::from(a.slerp(&b, 0.0)), &Mat4f::from(a));
+        assert_mtx_near(&Mat4f::from(a.slerp(&b, 1.0)), &Mat4f::from(b));
+        // -b is the same rotation, so the result must not take the long way around
+        assert_mtx_near(&Mat4f::from(a.slerp(&-b, 0.25)), &Mat4f::from(q));
+    }
+
+    #[test]
+    fn look_rotation() {
+        let forward = Vector3d::from_coords(1.0, 0.0, -1.0);
+        let q = Quaternion::look_rotation(&forward, &Vector3d::from_coords(0.0, 1.0, 0.0));
+        assert_vec_near(&q.rotate_vector(&Vector3d::from_coords(0.0, 0.0, -1.0)), &forward.normalize());
+        assert_vec_near(&q.rotate_vector(&Vector3d::from_coords(0.0, 1.0, 0.0)), &Vector3d::from_coords(0.0, 1.0, 0.0));
+    }
+}
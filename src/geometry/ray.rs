@@ -0,0 +1,116 @@
+use crate::geometry::{Point3d, Vector3d};
+
+/// A ray along with the [tmin; tmax] interval of distances where hits are of interest.
+/// The inverse direction and its signs are precomputed for the ray-box tests.
+#[derive(Copy, Clone)]
+pub struct Ray {
+    pub(crate) orig: Point3d,
+    pub(crate) dir: Vector3d,
+    pub(crate) dir_inv: Vector3d,
+    /// 1 if the respective component of the direction is negative, 0 otherwise
+    pub(crate) sign: [usize; 3],
+    pub(crate) tmin: f32,
+    pub(crate) tmax: f32,
+    /// The dimension where the direction is the largest goes last, for the watertight ray-triangle test
+    pub(crate) axes: [usize; 3],
+    /// Shear which turns the direction into +Z, and the scale of Z
+    pub(crate) shear: [f32; 3],
+}
+
+impl Ray {
+    pub fn new(orig: Point3d, dir: Vector3d) -> Self {
+        Ray::with_interval(orig, dir, 0.0, f32::MAX)
+    }
+
+    pub fn with_interval(orig: Point3d, dir: Vector3d, tmin: f32, tmax: f32) -> Self {
+        let dir_inv = Vector3d::from_coords(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let dir_coords = [dir.x, dir.y, dir.z];
+        let kz = if dir.x.abs() > dir.y.abs() {
+            if dir.x.abs() > dir.z.abs() { 0 } else { 2 }
+        } else if dir.y.abs() > dir.z.abs() {
+            1
+        } else {
+            2
+        };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        // Keeps the winding order of the triangles
+        if dir_coords[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+        Ray {
+            orig,
+            dir,
+            dir_inv,
+            sign: [
+                (dir_inv.x < 0.0) as usize,
+                (dir_inv.y < 0.0) as usize,
+                (dir_inv.z < 0.0) as usize,
+            ],
+            tmin,
+            tmax,
+            axes: [kx, ky, kz],
+            shear: [
+                dir_coords[kx] / dir_coords[kz],
+                dir_coords[ky] / dir_coords[kz],
+                1.0 / dir_coords[kz],
+            ],
+        }
+    }
+
+    pub fn get_origin(&self) -> Point3d {
+        self.orig
+    }
+    pub fn get_direction(&self) -> Vector3d {
+        self.dir
+    }
+
+    pub fn get_point_at(&self, t: f32) -> Point3d {
+        self.orig + self.dir * t
+    }
+
+    /// True if the distance belongs to [tmin; tmax]
+    #[inline]
+    pub fn contains(&self, t: f32) -> bool {
+        t >= self.tmin && t <= self.tmax
+    }
+
+    /// The smallest of the distances within [tmin; tmax]
+    pub(crate) fn get_closest(&self, distances: &[f32]) -> Option<f32> {
+        distances.iter()
+            .copied()
+            .filter(|&t| self.contains(t))
+            .fold(None, |res, t| match res {
+                Some(closest) if closest <= t => res,
+                _ => Some(t),
+            })
+    }
+
+    /// Rejects all the hits beyond t from now on, e.g. beyond the closest hit found so far
+    pub fn set_tmax(&mut self, t: f32) {
+        self.tmax = t;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::TraceablePrimitive;
+    use crate::geometry::sphere::Sphere;
+
+    #[test]
+    fn hits_outside_interval_are_rejected() {
+        let sphere = Sphere::new(Point3d::from_coords(0.0, 0.0, -5.0), 1.0);
+        let mut ray = Ray::new(Point3d::new(), Vector3d::from_coords(0.0, 0.0, -1.0));
+        assert_eq!(ray.sign, [0, 0, 1]);
+        assert_eq!(sphere.get_distance_to(&ray), Some(4.0));
+        let pt = ray.get_point_at(4.0);
+        assert_eq!((pt.x, pt.y, pt.z), (0.0, 0.0, -4.0));
+        ray.set_tmax(3.0);
+        assert!(sphere.get_distance_to(&ray).is_none());
+        assert!(sphere.get_bounding_box().get_distance_to(&ray).is_none());
+        // Starting inside the sphere, only the far side is in front of the ray
+        let ray = Ray::with_interval(Point3d::new(), Vector3d::from_coords(0.0, 0.0, -1.0), 4.5, f32::MAX);
+        assert_eq!(sphere.get_distance_to(&ray), Some(6.0));
+    }
+}
@@ -0,0 +1,153 @@
+//! Real roots of polynomials up to the 4th degree, after "Solving Quartics And Cubics
+//! For Graphics" by Schwarze (Graphics Gems). Double precision is used, since the quartics
+//! of the torus lose too many digits in f32.
+
+use std::f64::consts::PI;
+
+const EQN_EPS: f64 = 1e-9;
+
+#[inline]
+fn is_zero(x: f64) -> bool {
+    x.abs() < EQN_EPS
+}
+
+/// Roots of a x^2 + b x + c in ascending order
+pub(crate) fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if is_zero(a) {
+        return if is_zero(b) { Vec::new() } else { vec![-c / b] };
+    }
+    let p = b / (2.0 * a);
+    let q = c / a;
+    let d = p * p - q;
+    if is_zero(d) {
+        vec![-p]
+    } else if d < 0.0 {
+        Vec::new()
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![-sqrt_d - p, sqrt_d - p]
+    }
+}
+
+/// Roots of a x^3 + b x^2 + c x + d in ascending order
+pub(crate) fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if is_zero(a) {
+        return solve_quadratic(b, c, d);
+    }
+    // x^3 + A x^2 + B x + C
+    let (a, b, c) = (b / a, c / a, d / a);
+    // Substitute x = y - A/3 to get y^3 + 3p y + 2q
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+    let cb_p = p * p * p;
+    let disc = q * q + cb_p;
+
+    let mut roots = if is_zero(disc) {
+        if is_zero(q) {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if disc < 0.0 {
+        // Three real roots
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![t * phi.cos(), -t * (phi + PI / 3.0).cos(), -t * (phi - PI / 3.0).cos()]
+    } else {
+        let sqrt_d = disc.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    let sub = a / 3.0;
+    roots.iter_mut().for_each(|x| *x -= sub);
+    roots.sort_by(|x, y| x.partial_cmp(y).unwrap());
+    roots
+}
+
+/// Roots of a x^4 + b x^3 + c x^2 + d x + e in ascending order
+pub(crate) fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if is_zero(a) {
+        return solve_cubic(b, c, d, e);
+    }
+    // x^4 + A x^3 + B x^2 + C x + D
+    let (a, b, c, d) = (b / a, c / a, d / a, e / a);
+    // Substitute x = y - A/4 to get y^4 + p y^2 + q y + r
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + c;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * c / 4.0 + d;
+
+    let mut roots = if is_zero(r) {
+        // y (y^3 + p y + q) = 0
+        let mut roots = solve_cubic(1.0, 0.0, p, q);
+        roots.push(0.0);
+        roots
+    } else {
+        // Take a real root of the resolvent cubic and factor into two quadratics
+        let z = solve_cubic(1.0, -p / 2.0, -r, r * p / 2.0 - q * q / 8.0)[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if is_zero(u) {
+            0.0
+        } else if u > 0.0 {
+            u.sqrt()
+        } else {
+            return Vec::new();
+        };
+        let v = if is_zero(v) {
+            0.0
+        } else if v > 0.0 {
+            v.sqrt()
+        } else {
+            return Vec::new();
+        };
+        let v = if q < 0.0 { -v } else { v };
+        let mut roots = solve_quadratic(1.0, v, z - u);
+        roots.append(&mut solve_quadratic(1.0, -v, z + u));
+        roots
+    };
+
+    let sub = a / 4.0;
+    roots.iter_mut().for_each(|x| {
+        *x -= sub;
+        // The closed form is not precise enough when the roots are close, polish them
+        for _ in 0..2 {
+            let f = (((*x + a) * *x + b) * *x + c) * *x + d;
+            let df = ((4.0 * *x + 3.0 * a) * *x + 2.0 * b) * *x + c;
+            if df != 0.0 {
+                *x -= f / df;
+            }
+        }
+    });
+    roots.sort_by(|x, y| x.partial_cmp(y).unwrap());
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(roots: &[f64], expected: &[f64]) {
+        assert_eq!(roots.len(), expected.len(), "{:?} != {:?}", roots, expected);
+        for (r, e) in roots.iter().zip(expected.iter()) {
+            assert!((r - e).abs() < 1e-6, "{:?} != {:?}", roots, expected);
+        }
+    }
+
+    #[test]
+    fn polynomial_roots() {
+        assert_roots(&solve_quadratic(2.0, -2.0, -4.0), &[-1.0, 2.0]);
+        assert_roots(&solve_quadratic(1.0, 0.0, 1.0), &[]);
+        // (x - 1)(x - 2)(x + 3)
+        assert_roots(&solve_cubic(1.0, 0.0, -7.0, 6.0), &[-3.0, 1.0, 2.0]);
+        // (x - 1)(x^2 + 1)
+        assert_roots(&solve_cubic(2.0, -2.0, 2.0, -2.0), &[1.0]);
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(&solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0), &[1.0, 2.0, 3.0, 4.0]);
+        // (x^2 - 2)(x^2 + 1)
+        assert_roots(&solve_quartic(1.0, 0.0, -1.0, 0.0, -2.0), &[-2f64.sqrt(), 2f64.sqrt()]);
+        assert_roots(&solve_quartic(1.0, 0.0, 2.0, 0.0, 1.0), &[]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::geometry::{Point3d, Vector3d};
+use crate::geometry::{Point3d, Ray, Vector3d};
 use crate::geometry::TraceablePrimitive;
 use crate::geometry::aabb::Aabb;
 
@@ -9,15 +9,15 @@ pub struct Sphere {
 }
 
 impl Sphere {
-    pub fn _new(center: Point3d, radius: f32) -> Sphere {
+    pub fn new(center: Point3d, radius: f32) -> Sphere {
         Sphere { center, radius }
     }
 }
 
 impl TraceablePrimitive for Sphere {
-    fn get_distance_to(&self, ray_origin: &Point3d, ray_dir: &Vector3d) -> Option<f32> {
-        let l = self.center - *ray_origin;
-        let tca = l * *ray_dir;
+    fn get_distance_to(&self, ray: &Ray) -> Option<f32> {
+        let l = self.center - ray.orig;
+        let tca = l * ray.dir;
         let d_squared = l * l - tca * tca;
         if d_squared > (self.radius * self.radius) {
             return None;
@@ -25,9 +25,9 @@ impl TraceablePrimitive for Sphere {
         let thc = (self.radius * self.radius - d_squared).sqrt();
         let t0 = tca - thc;
         let t1 = tca + thc;
-        if t0 >= 0.0 {
+        if ray.contains(t0) {
             Some(t0)
-        } else if t0 < 0.0 && t1 >= 0.0 {
+        } else if ray.contains(t1) {
             Some(t1)
         } else {
             None
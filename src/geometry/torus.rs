@@ -0,0 +1,94 @@
+use crate::geometry::{Point3d, Ray, Vector3d};
+use crate::geometry::TraceablePrimitive;
+use crate::geometry::aabb::Aabb;
+use crate::geometry::roots;
+
+/// Torus lying in the XZ plane around the center, the Y axis goes through the hole.
+/// Other orientations are set by the transform of the SceneObj.
+#[derive(Copy, Clone)]
+pub struct Torus {
+    center: Point3d,
+    /// From the center to the middle of the tube
+    major_radius: f32,
+    /// Of the tube
+    minor_radius: f32,
+}
+
+impl Torus {
+    pub fn new(center: Point3d, major_radius: f32, minor_radius: f32) -> Torus {
+        Torus { center, major_radius, minor_radius }
+    }
+}
+
+impl TraceablePrimitive for Torus {
+    fn get_distance_to(&self, ray: &Ray) -> Option<f32> {
+        // The quartic loses precision far from the torus, so start from where the ray enters the box
+        let t_start = self.get_bounding_box().get_distance_to(ray)?;
+        let o = ray.get_point_at(t_start) - self.center;
+        let d = ray.dir;
+        let (ox, oy, oz) = (o.x as f64, o.y as f64, o.z as f64);
+        let (dx, dy, dz) = (d.x as f64, d.y as f64, d.z as f64);
+        let r2_major = (self.major_radius * self.major_radius) as f64;
+        let r2_minor = (self.minor_radius * self.minor_radius) as f64;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) for p = o + d t
+        let dd = dx * dx + dy * dy + dz * dz;
+        let e = ox * ox + oy * oy + oz * oz - r2_major - r2_minor;
+        let f = ox * dx + oy * dy + oz * dz;
+        let four_r2 = 4.0 * r2_major;
+        let roots = roots::solve_quartic(
+            dd * dd,
+            4.0 * dd * f,
+            2.0 * dd * e + 4.0 * f * f + four_r2 * dy * dy,
+            4.0 * f * e + 2.0 * four_r2 * oy * dy,
+            e * e - four_r2 * (r2_minor - oy * oy),
+        );
+        let distances: Vec<f32> = roots.into_iter().map(|t| t as f32 + t_start).collect();
+        ray.get_closest(&distances)
+    }
+
+    fn get_normal(&self, surface_pt: &Point3d) -> Vector3d {
+        let p = *surface_pt - self.center;
+        let s = p * p - self.major_radius * self.major_radius - self.minor_radius * self.minor_radius;
+        Vector3d::from_coords(
+            p.x * s,
+            p.y * (s + 2.0 * self.major_radius * self.major_radius),
+            p.z * s,
+        ).normalize()
+    }
+
+    fn get_bounding_box(&self) -> Aabb {
+        let outer = self.major_radius + self.minor_radius;
+        let extent = Vector3d::from_coords(outer, self.minor_radius, outer);
+        Aabb::from_point3d(self.center + -extent, self.center + extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn through_the_hole() {
+        let torus = Torus::new(Point3d::from_coords(0.0, 0.0, -10.0), 3.0, 1.0);
+        // Through the middle of the tube on both sides
+        let ray = Ray::new(Point3d::from_coords(-10.0, 0.0, -10.0), Vector3d::from_coords(1.0, 0.0, 0.0));
+        let t = torus.get_distance_to(&ray).unwrap();
+        assert!((t - 6.0).abs() < 1e-4);
+        let n = torus.get_normal(&ray.get_point_at(t));
+        assert!((n.x + 1.0).abs() < 1e-4);
+        // Starting in the hole, the inner side of the tube is hit
+        let ray = Ray::new(Point3d::from_coords(0.0, 0.0, -10.0), Vector3d::from_coords(1.0, 0.0, 0.0));
+        let t = torus.get_distance_to(&ray).unwrap();
+        assert!((t - 2.0).abs() < 1e-4);
+        assert!((torus.get_normal(&ray.get_point_at(t)).x + 1.0).abs() < 1e-4);
+        // Down through the hole
+        let ray = Ray::new(Point3d::from_coords(0.0, 10.0, -10.0), Vector3d::from_coords(0.0, -1.0, 0.0));
+        assert!(torus.get_distance_to(&ray).is_none());
+        // Down onto the top of the tube
+        let ray = Ray::new(Point3d::from_coords(3.0, 10.0, -10.0), Vector3d::from_coords(0.0, -1.0, 0.0));
+        let t = torus.get_distance_to(&ray).unwrap();
+        assert!((t - 9.0).abs() < 1e-4);
+        assert!((torus.get_normal(&ray.get_point_at(t)).y - 1.0).abs() < 1e-4);
+    }
+}
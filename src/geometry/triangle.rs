@@ -1,4 +1,4 @@
-use crate::geometry::{Point3d, Vector3d, min_of_three_f32, max_of_three_f32};
+use crate::geometry::{Hit, Mat4f, Point3d, Point4d, Ray, Vector3d, min_of_three_f32, max_of_three_f32};
 use crate::geometry::TraceablePrimitive;
 use crate::geometry::aabb::Aabb;
 
@@ -6,6 +6,12 @@ use crate::geometry::aabb::Aabb;
 pub struct Triangle {
     pub v: [Point3d; 3],
     normal: Vector3d,
+    /// For smooth shading, the face normal unless set otherwise
+    vtx_normals: [Vector3d; 3],
+    /// (u, v) texture coordinates of the vertices
+    tex_coords: [[f32; 2]; 3],
+    /// Single-sided triangles are invisible from the back, i.e. when the ray goes along the normal
+    double_sided: bool,
     //parent: &Object,
 }
 
@@ -17,56 +23,145 @@ impl Triangle {
         Triangle {
             v: [v0, v1, v2],
             normal,
+            vtx_normals: [normal; 3],
+            tex_coords: [[0.0; 2]; 3],
+            double_sided: true,
         }
     }
-
-    fn _get_uv(&self, ray_origin: &Point3d, ray_dir: &Vector3d) -> Option<(f32, f32)> {
-        if let Some((_, u, v)) = self.moller_trumbore(ray_origin, ray_dir) {
-            Some((u, v))
-        } else {
-            None
+    
+    pub fn with_normal(v0: Point3d, v1: Point3d, v2: Point3d, normal: Vector3d) -> Self {
+        Triangle {
+            v: [v0, v1, v2],
+            normal,
+            vtx_normals: [normal; 3],
+            tex_coords: [[0.0; 2]; 3],
+            double_sided: true,
         }
     }
     
-    fn moller_trumbore(&self, ray_origin: &Point3d, ray_dir: &Vector3d) -> Option<(f32, f32, f32)> {
-        const EPSILON: f32 = 0.001;
-        let v0v1 = self.v[1] - self.v[0];
-        let v0v2 = self.v[2] - self.v[0];
-        let pvec = ray_dir.crossprod(&v0v2);
-        let det = v0v1 * pvec;
-        
-        if det < EPSILON {
-            return None;
+    pub fn vertex_normals(mut self, vtx_normals: [Vector3d; 3]) -> Self {
+        self.vtx_normals = vtx_normals;
+        self
+    }
+    pub fn get_vertex_normals(&self) -> &[Vector3d; 3] {
+        &self.vtx_normals
+    }
+    /// The vertex normals blended with the barycentric coordinates of the point
+    pub fn interpolate_normal(&self, barycentric: &[f32; 3]) -> Vector3d {
+        (self.vtx_normals[0] * barycentric[0]
+            + self.vtx_normals[1] * barycentric[1]
+            + self.vtx_normals[2] * barycentric[2]).normalize()
+    }
+    
+    pub fn tex_coords(mut self, tex_coords: [[f32; 2]; 3]) -> Self {
+        self.tex_coords = tex_coords;
+        self
+    }
+    pub fn get_tex_coords(&self) -> &[[f32; 2]; 3] {
+        &self.tex_coords
+    }
+    pub fn interpolate_tex_coords(&self, barycentric: &[f32; 3]) -> [f32; 2] {
+        let t = &self.tex_coords;
+        [
+            t[0][0] * barycentric[0] + t[1][0] * barycentric[1] + t[2][0] * barycentric[2],
+            t[0][1] * barycentric[0] + t[1][1] * barycentric[1] + t[2][1] * barycentric[2],
+        ]
+    }
+    
+    /// The same triangle with the vertices and the normals transformed, the normal matrix
+    /// is the inverse transpose of the vertex one
+    pub fn transform(&self, mtx: &Mat4f, normal_mtx: &Mat4f) -> Self {
+        let transform_pt = |p: &Point3d| Point3d::from(mtx * Point4d::from(*p));
+        let transform_normal = |n: &Vector3d| (normal_mtx * *n).normalize();
+        Triangle {
+            v: [transform_pt(&self.v[0]), transform_pt(&self.v[1]), transform_pt(&self.v[2])],
+            normal: transform_normal(&self.normal),
+            vtx_normals: [
+                transform_normal(&self.vtx_normals[0]),
+                transform_normal(&self.vtx_normals[1]),
+                transform_normal(&self.vtx_normals[2]),
+            ],
+            ..*self
         }
+    }
+    
+    pub fn double_sided(mut self, double_sided: bool) -> Self {
+        self.double_sided = double_sided;
+        self
+    }
+    pub fn is_double_sided(&self) -> bool {
+        self.double_sided
+    }
+
+    /// Watertight ray-triangle intersection from "Watertight Ray/Triangle Intersection"
+    /// by Woop et al. The edge functions are evaluated in the ray space exactly the same way
+    /// for the triangles sharing an edge, so rays can't slip through the cracks between them.
+    /// Returns the distance and the barycentric coordinates of v[1] and v[2].
+    fn intersect(&self, ray: &Ray) -> Option<(f32, f32, f32)> {
+        let [kx, ky, kz] = ray.axes;
+        let [sx, sy, sz] = ray.shear;
+        
+        let rel = |p: &Point3d| [p.x - ray.orig.x, p.y - ray.orig.y, p.z - ray.orig.z];
+        let (a, b, c) = (rel(&self.v[0]), rel(&self.v[1]), rel(&self.v[2]));
+        
+        // Shear and scale the vertices so the ray goes along +Z from the origin
+        let ax = a[kx] - sx * a[kz];
+        let ay = a[ky] - sy * a[kz];
+        let bx = b[kx] - sx * b[kz];
+        let by = b[ky] - sy * b[kz];
+        let cx = c[kx] - sx * c[kz];
+        let cy = c[ky] - sy * c[kz];
         
-        let inv_det = 1.0 / det;
-        let tvec = *ray_origin - self.v[0];
-        let u = tvec * pvec * inv_det;
+        let mut u = cx * by - cy * bx;
+        let mut v = ax * cy - ay * cx;
+        let mut w = bx * ay - by * ax;
         
-        if u < 0.0 || u > 1.0 {
+        // The ray goes exactly through an edge, recompute in double precision
+        if u == 0.0 || v == 0.0 || w == 0.0 {
+            u = (cx as f64 * by as f64 - cy as f64 * bx as f64) as f32;
+            v = (ax as f64 * cy as f64 - ay as f64 * cx as f64) as f32;
+            w = (bx as f64 * ay as f64 - by as f64 * ax as f64) as f32;
+        }
+        
+        // Positive edge functions mean the ray sees the front side
+        let front = u >= 0.0 && v >= 0.0 && w >= 0.0;
+        let back = u <= 0.0 && v <= 0.0 && w <= 0.0;
+        if !(front || (back && self.double_sided)) {
             return None;
         }
         
-        let qvec = tvec.crossprod(&v0v1);
-        let v = *ray_dir * qvec * inv_det;
-        if v < 0.0 || u + v > 1.0 {
+        let det = u + v + w;
+        if det == 0.0 {
             return None;
         }
         
-        let t = v0v2 * qvec * inv_det;
-        Some((t, u, v))
+        let t_scaled = sz * (u * a[kz] + v * b[kz] + w * c[kz]);
+        let det_inv = 1.0 / det;
+        let t = t_scaled * det_inv;
+        if !ray.contains(t) {
+            return None;
+        }
+        Some((t, v * det_inv, w * det_inv))
     }
 }
 
 impl TraceablePrimitive for Triangle {
-    fn get_distance_to(&self, ray_origin: &Point3d, ray_dir: &Vector3d) -> Option<f32> {
-        if let Some((t, _, _)) = self.moller_trumbore(ray_origin, ray_dir) {
+    fn get_distance_to(&self, ray: &Ray) -> Option<f32> {
+        if let Some((t, _, _)) = self.intersect(ray) {
             Some(t)
         } else {
             None
         }
     }
 
+    fn get_hit(&self, ray: &Ray) -> Option<Hit> {
+        let (t, u, v) = self.intersect(ray)?;
+        Some(Hit {
+            barycentric: [1.0 - u - v, u, v],
+            ..Hit::new(t, ray.get_point_at(t), self.normal)
+        })
+    }
+
     fn get_normal(&self, _: &Point3d) -> Vector3d {
         // Vec3f::new(0.0, 0.0, 0.0)
         self.normal
@@ -87,3 +182,48 @@ impl TraceablePrimitive for Triangle {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ray_down(x: f32, y: f32) -> Ray {
+        Ray::new(Point3d::from_coords(x, y, 5.0), Vector3d::from_coords(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn front_and_back_faces() {
+        // The normal looks at +Z, i.e. towards the rays going down
+        let t = Triangle::new(
+            Point3d::from_coords(0.0, 0.0, 0.0),
+            Point3d::from_coords(1.0, 0.0, 0.0),
+            Point3d::from_coords(0.0, 1.0, 0.0),
+        );
+        let (dist, u, v) = t.intersect(&ray_down(0.25, 0.5)).unwrap();
+        assert!((dist - 5.0).abs() < 1e-5 && (u - 0.25).abs() < 1e-5 && (v - 0.5).abs() < 1e-5);
+        assert!(t.intersect(&ray_down(0.75, 0.5)).is_none());
+        let up = Ray::new(Point3d::from_coords(0.25, 0.25, -5.0), Vector3d::from_coords(0.0, 0.0, 1.0));
+        assert!(t.get_distance_to(&up).is_some());
+        assert!(t.double_sided(false).get_distance_to(&up).is_none());
+        assert!(t.double_sided(false).get_distance_to(&ray_down(0.25, 0.25)).is_some());
+    }
+
+    #[test]
+    fn watertight_shared_edge() {
+        // Two triangles of a tilted quad, the rays going exactly through the diagonal
+        // must hit at least one of them
+        let p = [
+            Point3d::from_coords(0.0, 0.0, 0.0),
+            Point3d::from_coords(1.0, 0.0, 0.3),
+            Point3d::from_coords(1.0, 1.0, 0.7),
+            Point3d::from_coords(0.0, 1.0, 0.1),
+        ];
+        let t0 = Triangle::new(p[0], p[1], p[2]);
+        let t1 = Triangle::new(p[0], p[2], p[3]);
+        for i in 0..=100 {
+            let x = i as f32 / 100.0;
+            let ray = ray_down(x, x);
+            assert!(t0.get_distance_to(&ray).is_some() || t1.get_distance_to(&ray).is_some(), "{}", x);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::geometry::Point3d;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 pub struct Vector3d {
     pub(crate) x: f32,
     pub(crate) y: f32,
@@ -1,13 +1,10 @@
-//
-//
-//
-// #[cfg(test)]
-// mod test {
-// 	extern crate rayon;
-// 	use rayon::prelude::*;
-//
-// 	#[test]
-// 	fn t1 () {
-//
-// 	}
-// }
\ No newline at end of file
+extern crate image;
+extern crate rayon;
+
+pub mod geometry;
+pub mod scene;
+pub mod img_tiles;
+pub mod rasterizer;
+pub mod vertex_processor;
+pub mod sampler;
+pub mod tone_mapping;
@@ -1,8 +1,6 @@
 extern crate image;
-extern crate rayon;
-
-use rayon::prelude::*;
 
+use std::io::{self, Write};
 use std::time::Instant;
 
 //use std::ops::Deref;
@@ -12,29 +10,41 @@ use std::sync::{Arc};
 use image::{ImageBuffer, Rgb};
 
 //use scene::{IntoTriangles, mesh};
-use scene::light::Light;
-use scene::mesh::Mesh;
-use scene::shading;
-
-use crate::geometry::{Mat4f, Point3d, Point4d};
-use crate::geometry::triangle::Triangle;
-//use crate::img_tiles::{Tile, TileGenerator, TilesLayout};
-use crate::scene::{Scene};
-use crate::scene::wfobj;
-
-mod img_tiles;
-mod geometry;
-mod scene;
+use pixodel::scene::light::Light;
+use pixodel::scene::shading;
+
+use pixodel::geometry::Point3d;
+use pixodel::geometry::triangle::Triangle;
+use pixodel::img_tiles::TilesLayout;
+use pixodel::rasterizer::Rasterizer;
+use pixodel::vertex_processor::PhongShader;
+use pixodel::scene::{Scene};
+use pixodel::scene::camera::Camera;
+use pixodel::scene::material::Material;
+use pixodel::scene::texture::Texture;
+use pixodel::scene::wfobj;
+use pixodel::tone_mapping::{Operator, ToneMapper};
 
 //const NUM_SLAVES: u32 = 8;
 
 const FRAME_WIDTH: u32 = 640;
 const FRAME_HEIGHT: u32 = 640;
 
-// const TILE_WIDTH: u32 = 32;
-// const TILE_HEIGHT: u32 = TILE_WIDTH;
+const TILE_WIDTH: u32 = 32;
+const TILE_HEIGHT: u32 = TILE_WIDTH;
+
+const Z_NEAR: f32 = 0.1;
+const Z_FAR: f32 = 1000.0;
 
-fn create_scene_mesh() -> Mesh {
+const SAMPLES_PER_PIXEL: u32 = 1;
+const APERTURE_RADIUS: f32 = 0.0;
+const FOCUS_DISTANCE: f32 = 30.0;
+
+/// In stops
+const EXPOSURE: f32 = 0.0;
+const TONE_MAPPING: Operator = Operator::Aces;
+
+fn create_scene() -> Scene {
     
 
     // scene.add_obj(Box::new(Sphere::new(Vec3f::new(10.0, 10.0, -100.0), 10.0)));
@@ -63,34 +73,40 @@ fn create_scene_mesh() -> Mesh {
     //scene.add_wavefront_obj("models/cube2.obj");
     //scene.add_wavefront_obj("models/african_head.obj");
 
-    let head_model = scene::WfObj::new(Arc::new(wfobj::new_wavefront_obj("models/african_head.obj").unwrap()));
-    let head_0 = scene::SceneObj::new(&head_model)
+    let head_model = pixodel::scene::WfObj::load("models/african_head.obj").unwrap();
+    if head_model.get_num_skipped() > 0 {
+        println!("Skipped {} points and lines of the head model", head_model.get_num_skipped());
+    }
+    let head_texture = Arc::new(Texture::load("models/african_head_diffuse.tga").unwrap());
+    let head_0 = pixodel::scene::SceneObj::new(&head_model)
+        .material(Material::new().texture(head_texture.clone()))
         .scale(7.0, 7.0, 7.0)
         .rotate(0.0, 0.0, 0.0)
         .translate(3.0, 0.0, -30.0);
-    let head_1 = scene::SceneObj::new(&head_model)
+    let head_1 = pixodel::scene::SceneObj::new(&head_model)
+        .material(Material::new().texture(head_texture))
         .scale(7.0, 7.0, 7.0)
         .rotate(0.0, 0.0, 0.0)
         .translate(-3.0, 0.0, -30.0);
     
     
     
-    let cube_model = scene::WfObj::new(Arc::new(wfobj::new_wavefront_obj("models/cube.obj").unwrap()));
-    let _cube_0 = scene::SceneObj::new(&cube_model)
+    let cube_model = pixodel::scene::WfObj::new(Arc::new(wfobj::new_wavefront_obj("models/cube.obj").unwrap()));
+    let _cube_0 = pixodel::scene::SceneObj::new(&cube_model)
         .scale(4.0, 4.0, 4.0)
         .rotate(45.0, 45.0, 0.0)
         .translate(5.0, 0.0, -30.0);
-    let _cube_1 = scene::SceneObj::new(&cube_model)
+    let _cube_1 = pixodel::scene::SceneObj::new(&cube_model)
         .scale(4.0, 4.0, 4.0)
         .rotate(45.0, 45.0, 0.0)
         .translate(-5.0, 0.0, -30.0);
     
-    let triangle_model = scene::TriObj::new(Triangle::new(
+    let triangle_model = pixodel::scene::TriObj::new(Triangle::new(
         Point3d::from_coords(-1.0, 1.0, 0.0),
         Point3d::from_coords(0.0, -1.0, 0.0),
         Point3d::from_coords(1.0, 0.8, 0.0),
     ));
-    let _tri_0 = scene::SceneObj::new(&triangle_model)
+    let _tri_0 = pixodel::scene::SceneObj::new(&triangle_model)
         .scale(10.0, 10.0, 10.0)
         .rotate(-45.0, 0.0, 0.0)
         .translate(0.0, 0.0, -40.0);
@@ -103,8 +119,18 @@ fn create_scene_mesh() -> Mesh {
         //.add_obj(Box::new(tri_0))
         //.add_light(Light::new(Point3d::from_coords(-50.0, -50.0, 50.0), 0.5))
         //.add_light(Light::new(Point3d::from_coords(10.0, 200.0, 20.0), 0.5))
-        .add_light(Light::new(Point3d::from_coords(1.0, 0.0, 10.0), 0.5))
-        .to_mesh()
+        .add_light(Light::new(Point3d::from_coords(1.0, 0.0, 10.0), 1.0).area(1.0, 16))
+}
+
+/// The colors are linear, they are tone mapped and sRGB encoded only here
+fn save_image(fbuf: &[[f32; 3]], frame_width: u32, frame_height: u32, path: &str) {
+    let fbuf = ToneMapper::new()
+        .exposure(EXPOSURE)
+        .operator(TONE_MAPPING)
+        .get_rgb8_frame(fbuf);
+    let mut img: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_vec(frame_width, frame_height, fbuf).unwrap();
+    image::imageops::flip_vertical_in_place(&mut img);
+    img.save(path).unwrap();
 }
 
 //type VtxShader = Box<dyn FnOnce(Point3d, Point3d, Vector3d, &Vec<Light>) -> f32 + Send + 'static>;
@@ -118,51 +144,64 @@ fn main() {
     let frame_width = FRAME_WIDTH;
     let frame_height = FRAME_HEIGHT;
     
-    let aspect_ratio = (frame_width as f32) / (frame_height as f32);
-    let fov_vert: f32 = 35.0;
-    let fov_scaling_factor = (fov_vert / 2.0).to_radians().tan();
-    
-    // First scale from the viewport shape to NDC: [0; screen] -> [0; 2]
-    let screen_to_world = Mat4f::from_rows(
-        [2.0 * fov_scaling_factor * aspect_ratio / frame_width as f32, 0.0, 0.0, -fov_scaling_factor * aspect_ratio],
-        [0.0, 2.0 * fov_scaling_factor / frame_height as f32, 0.0, -fov_scaling_factor],
-        [0.0, 0.0, 1.0, 0.0],
-        [0.0, 0.0, 0.0, 1.0],
-    );
-    
-    let ray_orig = Point3d::from_coords(0.0, 0.0, 0.0);
+    let camera = Camera::new(frame_width, frame_height)
+        .position(0.0, 0.0, 0.0)
+        .look_at(0.0, 0.0, -30.0)
+        .fov(35.0)
+        .lens(APERTURE_RADIUS, FOCUS_DISTANCE)
+        .samples_per_pixel(SAMPLES_PER_PIXEL)
+        .clip_planes(Z_NEAR, Z_FAR);
     
     let recursion_depth = 4;
     
-    loop {
-        //let mesh_glob = Arc::new(create_scene_mesh());
-        let mesh_glob = create_scene_mesh();
-    
-        let timer = Instant::now();
-    
-        let mut fbuf: Vec<[u8; 3]> = vec![[0, 0, 0]; (frame_width * frame_height) as usize];
-        fbuf.par_iter_mut().enumerate().for_each(|(idx, pix)| {
-            let x = idx as u32 % frame_width;
-            let y = idx as u32 / frame_width;
-            let ray_aim = Point3d::from(&screen_to_world * Point4d::from_coords(x as f32, y as f32, -1.0, 1.0));
-            let ray_dir = ray_aim - ray_orig;
-            let color = mesh_glob.cast_ray(
-                &ray_orig,
-                &ray_dir.normalize(),
-                &|a, b, c, d| shading::phong(a, b, c, d),
-                recursion_depth,
-            );
-            *pix = color;
-        });
-    
-        println!("Elapsed time: {:.2?}", timer.elapsed());
-        
-        //let fbuf = fbuf.iter().flatten().map(|x| *x).collect();
-        let fbuf = fbuf.iter().flat_map(|x| *x).collect();
-    
-        let mut img: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_vec(frame_width, frame_height, fbuf).unwrap();
-        image::imageops::flip_vertical_in_place(&mut img);
-        img.save("myimg.png").unwrap();
-        break;
-    }
+    let scene = create_scene();
+    
+    let layout = TilesLayout::new(frame_width, frame_height, TILE_WIDTH, TILE_HEIGHT);
+    
+    let timer = Instant::now();
+    let rasterizer = Rasterizer::new(layout);
+    let fbuf = rasterizer.render(&scene, &camera, &PhongShader, &PhongShader);
+    println!("Rasterization time: {:.2?}", timer.elapsed());
+    save_image(&fbuf, frame_width, frame_height, "preview.png");
+    
+    let mesh_glob = scene.to_mesh();
+    
+    let timer = Instant::now();
+    
+    let num_tiles = layout.num_tiles_in_row * layout.num_tiles_in_col;
+    let mut fbuf: Vec<[f32; 3]> = vec![[0.0; 3]; (frame_width * frame_height) as usize];
+    layout.render_tiles(
+        &mut fbuf,
+        |tile| {
+            let (origin_x, origin_y) = layout.get_tile_origin(tile);
+            for y in origin_y..origin_y + tile.height {
+                for x in origin_x..origin_x + tile.width {
+                    let rays = camera.get_pixel_rays(x, y);
+                    let mut color_sum = [0.0; 3];
+                    for ray in rays.iter() {
+                        let color = mesh_glob.cast_ray(
+                            ray,
+                            &|a, b, c, d, e, f, g| shading::phong(a, b, c, d, e, f, g),
+                            recursion_depth,
+                        );
+                        color_sum.iter_mut().zip(color.iter()).for_each(|(sum, &c)| *sum += c);
+                    }
+                    let num_rays = rays.len().max(1) as f32;
+                    tile.vbuf.push([
+                        color_sum[0] / num_rays,
+                        color_sum[1] / num_rays,
+                        color_sum[2] / num_rays,
+                    ]);
+                }
+            }
+        },
+        |num_done| {
+            print!("\rTraced {}/{} tiles", num_done, num_tiles);
+            io::stdout().flush().unwrap();
+        },
+    );
+    
+    println!();
+    println!("Ray tracing time: {:.2?}", timer.elapsed());
+    save_image(&fbuf, frame_width, frame_height, "myimg.png");
 }
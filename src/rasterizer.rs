@@ -0,0 +1,276 @@
+use crate::geometry::{Mat4f, Point3d, Point4d};
+use crate::geometry::triangle::Triangle;
+use crate::img_tiles::{Tile, TileGenerator, TilesLayout};
+use crate::scene::{Scene, SceneObj, BG_COLOR};
+use crate::scene::camera::Camera;
+use crate::vertex_processor::{PixShader, Uniforms, Varying, VtxAttr, VtxShader};
+
+/// A triangle after the vertex processing stage
+struct ScreenTriangle {
+	/// X and Y are in pixels, Z is the [0; 1] depth
+	v: [Point3d; 3],
+	/// 1/W of every vertex in the clip space, used for perspective-correct interpolation
+	w_inv: [f32; 3],
+	varyings: [Varying; 3],
+	/// Index of the uniforms of the object and the material the triangle belongs to
+	uniforms_idx: usize,
+	/// Bounding box in pixels, clipped to the screen: min X, min Y, max X, max Y
+	bbox: (u32, u32, u32, u32),
+}
+
+impl ScreenTriangle {
+	/// Twice the signed area of the triangle, its sign tells the winding order
+	fn get_area(&self) -> f32 {
+		edge_function(&self.v[0], &self.v[1], self.v[2].x, self.v[2].y)
+	}
+
+	/// Barycentric coordinates of the point, negative if the point is outside the triangle
+	fn get_barycentric(&self, x: f32, y: f32, area_inv: f32) -> [f32; 3] {
+		[
+			edge_function(&self.v[1], &self.v[2], x, y) * area_inv,
+			edge_function(&self.v[2], &self.v[0], x, y) * area_inv,
+			edge_function(&self.v[0], &self.v[1], x, y) * area_inv,
+		]
+	}
+}
+
+#[inline]
+fn edge_function(a: &Point3d, b: &Point3d, x: f32, y: f32) -> f32 {
+	(b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+pub struct Rasterizer {
+	layout: TilesLayout,
+	viewport: Mat4f,
+}
+
+impl Rasterizer {
+	pub fn new(layout: TilesLayout) -> Self {
+		let viewport = Mat4f::viewport(layout.frame_width as f32, layout.frame_height as f32);
+		Rasterizer {
+			layout,
+			viewport,
+		}
+	}
+
+	/// Renders the scene seen by the camera.
+	/// Returns the frame buffer, the first pixel is the bottom-left one.
+	pub fn render(&self, scene: &Scene, camera: &Camera, vtx_shader: &impl VtxShader, pix_shader: &impl PixShader) -> Vec<[f32; 3]> {
+		let view = camera.get_view_mtx();
+		let projection = camera.get_projection_mtx();
+		let objects = scene.flatten();
+		// Every object gets the uniforms for its own material, followed by the ones for each
+		// of the materials of its model
+		let uniforms: Vec<Uniforms> = objects.iter()
+			.flat_map(|&(obj, model)| {
+				let materials = std::iter::once(obj.get_material()).chain(obj.get_materials().iter().map(|m| m.as_ref()));
+				materials.map(move |material| Uniforms {
+					model,
+					model_normal: model.get_normal_mtx(),
+					view,
+					projection,
+					camera_pt: camera.get_position(),
+					lights: &scene.lights,
+					material,
+				})
+			})
+			.collect();
+		let triangles = self.process_triangles(&objects, &uniforms, vtx_shader);
+		let tiles: Vec<Tile> = TileGenerator::new(0, 1, &self.layout).collect();
+		let bins = self.bin_triangles(&triangles, &tiles);
+
+		let mut fbuf = vec![BG_COLOR; (self.layout.frame_width * self.layout.frame_height) as usize];
+		self.layout.render_tiles(
+			&mut fbuf,
+			|t| {
+				let bin = &bins[self.layout.get_tile_idx(t)];
+				self.rasterize_tile(t, bin, &triangles, &uniforms, pix_shader);
+			},
+			|_| (),
+		);
+		fbuf
+	}
+
+	/// Runs the vertex shader for every triangle of the scene and transforms the result
+	/// to the screen space, dropping the triangles which have at least one vertex outside
+	/// of the near and far planes or entirely off the screen.
+	fn process_triangles(&self, objects: &[(&SceneObj, Mat4f)], uniforms: &[Uniforms], vtx_shader: &impl VtxShader) -> Vec<ScreenTriangle> {
+		let mut first_uniforms_idx = 0;
+		objects.iter()
+			.flat_map(|(obj, _)| {
+				let obj_uniforms_idx = first_uniforms_idx;
+				first_uniforms_idx += 1 + obj.get_materials().len();
+				obj.triangles.iter()
+					.zip(obj.material_indices.iter())
+					.map(move |(t, material_idx)| (obj_uniforms_idx + material_idx.map_or(0, |idx| idx + 1), t))
+			})
+			.filter_map(|(uniforms_idx, t)| self.process_triangle(t, uniforms_idx, &uniforms[uniforms_idx], vtx_shader))
+			.collect()
+	}
+
+	fn process_triangle(&self, t: &Triangle, uniforms_idx: usize, uniforms: &Uniforms, vtx_shader: &impl VtxShader) -> Option<ScreenTriangle> {
+		let mut v = [Point3d::new(); 3];
+		let mut w_inv = [0.0; 3];
+		let mut varyings = [Varying::new(), Varying::new(), Varying::new()];
+		for i in 0..3 {
+			let vtx = VtxAttr {
+				vtx_coords: t.v[i],
+				norm_coords: t.get_vertex_normals()[i],
+				txt_coords: t.get_tex_coords()[i],
+			};
+			let clip = vtx_shader.vertex(uniforms, &vtx, &mut varyings[i]);
+			if clip.w <= 0.0 || clip.z < -clip.w || clip.z > clip.w {
+				return None;
+			}
+			w_inv[i] = 1.0 / clip.w;
+			let ndc = Point4d::from(Point3d::from(clip));
+			v[i] = Point3d::from(&self.viewport * ndc);
+		}
+		let bbox = self.get_screen_bbox(&v)?;
+		Some(ScreenTriangle {
+			v,
+			w_inv,
+			varyings,
+			uniforms_idx,
+			bbox,
+		})
+	}
+
+	/// Creates a list of triangles for every tile of the frame.
+	/// The tiles are expected in the order of their indices, as TileGenerator makes them.
+	fn bin_triangles(&self, triangles: &[ScreenTriangle], tiles: &[Tile]) -> Vec<Vec<usize>> {
+		let mut bins = vec![Vec::new(); tiles.len()];
+		for (idx, t) in triangles.iter().enumerate() {
+			let area = t.get_area();
+			if area == 0.0 {
+				continue;
+			}
+			// Only the tiles under the bounding box
+			let (min_x, min_y, max_x, max_y) = t.bbox;
+			let (first_row_idx, first_col_idx) = self.layout.get_tile_at(min_x, min_y);
+			let (last_row_idx, last_col_idx) = self.layout.get_tile_at(max_x, max_y);
+			let covered_tiles = (first_col_idx..=last_col_idx)
+				.flat_map(|col_idx| (first_row_idx..=last_row_idx).map(move |row_idx| col_idx * self.layout.num_tiles_in_row + row_idx));
+			for tile in covered_tiles.map(|tile_idx| &tiles[tile_idx as usize]) {
+				let (x0, y0) = self.layout.get_tile_origin(tile);
+				let (x1, y1) = (x0 + tile.width, y0 + tile.height);
+				let corners = [
+					(x0 as f32, y0 as f32),
+					(x1 as f32, y0 as f32),
+					(x0 as f32, y1 as f32),
+					(x1 as f32, y1 as f32),
+				];
+				// The tile is outside if all four corners are on the outer side of the same edge
+				let is_outside = (0..3).any(|e| {
+					let a = &t.v[e];
+					let b = &t.v[(e + 1) % 3];
+					corners.iter().all(|&(x, y)| edge_function(a, b, x, y) * area < 0.0)
+				});
+				if !is_outside {
+					bins[self.layout.get_tile_idx(tile)].push(idx);
+				}
+			}
+		}
+		bins
+	}
+
+	/// Bounding box of the triangle in pixels, clipped to the screen
+	fn get_screen_bbox(&self, v: &[Point3d; 3]) -> Option<(u32, u32, u32, u32)> {
+		let min_x = v.iter().map(|v| v.x).fold(f32::MAX, f32::min).max(0.0);
+		let min_y = v.iter().map(|v| v.y).fold(f32::MAX, f32::min).max(0.0);
+		let max_x = v.iter().map(|v| v.x).fold(f32::MIN, f32::max).min(self.layout.frame_width as f32 - 1.0);
+		let max_y = v.iter().map(|v| v.y).fold(f32::MIN, f32::max).min(self.layout.frame_height as f32 - 1.0);
+		if min_x > max_x || min_y > max_y {
+			None
+		} else {
+			Some((min_x as u32, min_y as u32, max_x as u32, max_y as u32))
+		}
+	}
+
+	fn rasterize_tile(&self, tile: &mut Tile, bin: &[usize], triangles: &[ScreenTriangle], uniforms: &[Uniforms], pix_shader: &impl PixShader) {
+		let (origin_x, origin_y) = self.layout.get_tile_origin(tile);
+		let tile_size = (tile.width * tile.height) as usize;
+		tile.vbuf.resize(tile_size, BG_COLOR);
+		let mut zbuf = vec![f32::MAX; tile_size];
+		let mut varying = Varying::new();
+
+		for &idx in bin {
+			let t = &triangles[idx];
+			let area_inv = 1.0 / t.get_area();
+			let (min_x, min_y, max_x, max_y) = t.bbox;
+			let x_range = min_x.max(origin_x)..=max_x.min(origin_x + tile.width - 1);
+			let y_range = min_y.max(origin_y)..=max_y.min(origin_y + tile.height - 1);
+			for y in y_range {
+				for x in x_range.clone() {
+					// Sample in the center of the pixel
+					let bary = t.get_barycentric(x as f32 + 0.5, y as f32 + 0.5, area_inv);
+					if bary.iter().any(|&b| b < 0.0) {
+						continue;
+					}
+					let z = bary[0] * t.v[0].z + bary[1] * t.v[1].z + bary[2] * t.v[2].z;
+					let pix_idx = ((y - origin_y) * tile.width + (x - origin_x)) as usize;
+					if z >= zbuf[pix_idx] {
+						continue;
+					}
+					zbuf[pix_idx] = z;
+
+					let persp = [bary[0] * t.w_inv[0], bary[1] * t.w_inv[1], bary[2] * t.w_inv[2]];
+					let persp_norm = 1.0 / (persp[0] + persp[1] + persp[2]);
+					let weights = [persp[0] * persp_norm, persp[1] * persp_norm, persp[2] * persp_norm];
+					varying.interpolate(&t.varyings, &weights);
+					tile.vbuf[pix_idx] = pix_shader.fragment(&uniforms[t.uniforms_idx], &varying);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::scene::TriObj;
+	use crate::scene::light::Light;
+	use crate::vertex_processor::PhongShader;
+
+	#[test]
+	fn triangle_covers_center() {
+		let rasterizer = Rasterizer::new(TilesLayout::new(64, 48, 16, 16));
+		let camera = Camera::new(64, 48).fov(90.0);
+		let tri = TriObj::new(Triangle::new(
+			Point3d::from_coords(-1.0, -1.0, -2.0),
+			Point3d::from_coords(1.0, -1.0, -2.0),
+			Point3d::from_coords(0.0, 1.0, -2.0),
+		));
+		let scene = Scene::new()
+			.add_obj(SceneObj::new(&tri).scale(1.0, 1.0, 1.0))
+			.add_light(Light::new(Point3d::from_coords(0.0, 0.0, 0.0), 1.0));
+		let fbuf = rasterizer.render(&scene, &camera, &PhongShader, &PhongShader);
+		assert_eq!(fbuf.len(), 64 * 48);
+		assert_ne!(fbuf[24 * 64 + 32], BG_COLOR);
+		assert_eq!(fbuf[0], BG_COLOR);
+		assert_eq!(fbuf[47 * 64 + 63], BG_COLOR);
+	}
+
+	#[test]
+	fn binning() {
+		let rasterizer = Rasterizer::new(TilesLayout::new(64, 48, 16, 16));
+		let tiles: Vec<Tile> = TileGenerator::new(0, 1, &rasterizer.layout).collect();
+		let v = [
+			Point3d::from_coords(20.0, 20.0, 0.5),
+			Point3d::from_coords(40.0, 20.0, 0.5),
+			Point3d::from_coords(20.0, 40.0, 0.5),
+		];
+		let t = ScreenTriangle {
+			v,
+			w_inv: [1.0; 3],
+			varyings: [Varying::new(), Varying::new(), Varying::new()],
+			uniforms_idx: 0,
+			bbox: rasterizer.get_screen_bbox(&v).unwrap(),
+		};
+		let bins = rasterizer.bin_triangles(&[t], &tiles);
+		// Under the bounding box are the tiles 5 and 6 of the second row and 9 and 10 of the third,
+		// the triangle doesn't reach the corner of the last one
+		let binned: Vec<usize> = (0..bins.len()).filter(|&i| !bins[i].is_empty()).collect();
+		assert_eq!(binned, vec![5, 6, 9]);
+	}
+}
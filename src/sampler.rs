@@ -0,0 +1,89 @@
+/// Generates 2D points of the R2 low-discrepancy sequence in [0; 1)^2. It is deterministic,
+/// so renders are reproducible and no random number generator is needed.
+/// Every seed shifts the whole sequence by a different offset (Cranley-Patterson rotation),
+/// so neighbouring pixels don't share the same pattern.
+pub struct Sampler {
+	offset: (f32, f32),
+	idx: u32,
+}
+
+// 1/g and 1/g^2, where g is the plastic constant
+const R2_ALPHA: (f32, f32) = (0.754_877_7, 0.569_840_3);
+
+impl Sampler {
+	pub fn new(seed: u32) -> Self {
+		let h0 = hash(seed);
+		let h1 = hash(h0);
+		Sampler {
+			offset: (to_unit_f32(h0), to_unit_f32(h1)),
+			idx: 0,
+		}
+	}
+
+	pub fn next_2d(&mut self) -> (f32, f32) {
+		let n = self.idx as f32;
+		self.idx += 1;
+		(
+			(self.offset.0 + n * R2_ALPHA.0).fract(),
+			(self.offset.1 + n * R2_ALPHA.1).fract(),
+		)
+	}
+}
+
+/// PCG-style integer hash
+pub fn hash(val: u32) -> u32 {
+	let state = val.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+	let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277_803_737);
+	(word >> 22) ^ word
+}
+
+fn to_unit_f32(val: u32) -> f32 {
+	(val >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/// Maps a point of the unit square to the unit disk preserving the uniform distribution
+/// (the concentric mapping by Shirley and Chiu)
+pub fn square_to_disk(u: f32, v: f32) -> (f32, f32) {
+	use std::f32::consts::FRAC_PI_4;
+	let a = 2.0 * u - 1.0;
+	let b = 2.0 * v - 1.0;
+	if a == 0.0 && b == 0.0 {
+		return (0.0, 0.0);
+	}
+	let (r, phi) = if a.abs() > b.abs() {
+		(a, FRAC_PI_4 * (b / a))
+	} else {
+		(b, 2.0 * FRAC_PI_4 - FRAC_PI_4 * (a / b))
+	};
+	(r * phi.cos(), r * phi.sin())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn samples_in_unit_square() {
+		let mut s = Sampler::new(42);
+		let mut sum = (0.0, 0.0);
+		for _ in 0..1024 {
+			let (u, v) = s.next_2d();
+			assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+			sum.0 += u;
+			sum.1 += v;
+		}
+		assert!((sum.0 / 1024.0 - 0.5).abs() < 0.01);
+		assert!((sum.1 / 1024.0 - 0.5).abs() < 0.01);
+	}
+
+	#[test]
+	fn disk_mapping() {
+		assert_eq!(square_to_disk(0.5, 0.5), (0.0, 0.0));
+		for &(u, v) in &[(0.0, 0.0), (1.0, 0.5), (0.3, 0.9), (0.99, 0.01)] {
+			let (x, y) = square_to_disk(u, v);
+			assert!(x * x + y * y <= 1.0 + 1e-6);
+		}
+		let (x, y) = square_to_disk(1.0, 0.5);
+		assert!((x - 1.0).abs() < 1e-6 && y.abs() < 1e-6);
+	}
+}
@@ -3,34 +3,37 @@
 use light::Light;
 use mesh::Mesh;
 
-use crate::geometry::{Mat4f, Point3d, Point4d, TraceablePrimitive};
+use std::sync::Arc;
+
+use crate::geometry::{Instance, Mat4f, Point3d, Quaternion, TraceablePrimitive, Vector3d};
 //use crate::geometry::aabb::Aabb;
 use crate::geometry::triangle::Triangle;
 pub use crate::scene::triangle::TriObj;
 pub use crate::scene::wfobj::WfObj;
+use crate::scene::material::Material;
 
+pub mod camera;
 pub mod light;
 pub mod wfobj;
 pub mod triangle;
 //pub mod tracing;
-pub(crate) mod shading;
+pub mod shading;
+pub mod texture;
+pub mod mtl;
+pub mod material;
 mod bvhtree;
-pub(crate) mod mesh;
+pub mod mesh;
+
+/// Linear color where no object is seen, dark gray once sRGB encoded. Shared by the renderers.
+pub(crate) const BG_COLOR: [f32; 3] = [0.012; 3];
 
+#[derive(Default)]
 pub struct Scene {
     pub lights: Vec<Light>,
     pub objects: Vec<SceneObj>,
 }
 
 
-// #[repr(C, align(32))]
-// struct VtxAttr {
-//     vtx_coords: Point3d,
-//     norm_coords: Vector3d,
-//     txt_coords: Point3d,
-// }
-
-
 impl Scene {
     pub fn new() -> Self {
         Scene {
@@ -46,30 +49,80 @@ impl Scene {
         self.lights.push(light);
         self
     }
+    
+    /// Looks for the object with the given name in the whole hierarchy
+    pub fn find_obj(&self, name: &str) -> Option<&SceneObj> {
+        self.objects.iter().find_map(|obj| obj.find(name))
+    }
+    pub fn find_obj_mut(&mut self, name: &str) -> Option<&mut SceneObj> {
+        self.objects.iter_mut().find_map(|obj| obj.find_mut(name))
+    }
+    
+    /// Model-to-world matrix of the named object, which includes the transforms of all its parents
+    pub fn get_model_to_world_mtx(&self, name: &str) -> Option<Mat4f> {
+        self.flatten().into_iter()
+            .find(|(obj, _)| obj.name == name)
+            .map(|(_, mtx)| mtx)
+    }
+    pub fn get_world_to_model_mtx(&self, name: &str) -> Option<Mat4f> {
+        self.get_model_to_world_mtx(name)?.inverse()
+    }
+    
+    /// Every object of the hierarchy along with its model-to-world matrix, parents go before their children
+    pub fn flatten(&self) -> Vec<(&SceneObj, Mat4f)> {
+        let mut res = Vec::new();
+        let mut stack: Vec<(&SceneObj, Mat4f)> = self.objects.iter()
+            .rev()
+            .map(|obj| (obj, obj.set_model_mtx()))
+            .collect();
+        while let Some((obj, model_to_world)) = stack.pop() {
+            for child in obj.children.iter().rev() {
+                stack.push((child, &model_to_world * &child.set_model_mtx()));
+            }
+            res.push((obj, model_to_world));
+        }
+        res
+    }
 
+    /// Triangles go first, then the other primitives. The indices of the BVH, of the bounding boxes
+    /// and of the owning objects follow the same order.
     pub fn to_mesh(&self) -> Mesh {
         let mut mesh = Mesh::new();
-        //let mut tmp: Vec<BvhInfo> = Vec::new();
-        for obj in &self.objects {
-            for t in obj.apply_model_transform().iter() {
-                mesh.triangles.push(t.clone());
-                mesh.b_boxes.push(t.get_bounding_box());
-                /*let info = BvhInfo {
-                    bb: t.get_bounding_box(),
-                    centroid: t.get_bounding_box().get_centroid(),
-                    triangle: t,
-                };
-                mesh.bounding_box.get_superset(info.bb);
-                tmp.push(info);*/
+        let mut shape_obj_indices = Vec::new();
+        let mut shape_material_indices = Vec::new();
+        for (obj_idx, (obj, model_to_world)) in self.flatten().into_iter().enumerate() {
+            // The material of the object goes first, then the ones of its model
+            let obj_material_idx = mesh.materials.len();
+            mesh.materials.push(Arc::new(obj.material.clone()));
+            mesh.materials.extend(obj.materials.iter().cloned());
+            for (t, material_idx) in obj.apply_model_transform(&model_to_world).iter().zip(obj.material_indices.iter()) {
+                mesh.triangles.push(*t);
+                mesh.material_indices.push(material_idx.map_or(obj_material_idx, |idx| obj_material_idx + 1 + idx));
+                mesh.obj_indices.push(obj_idx);
+            }
+            for prim in obj.primitives.iter() {
+                mesh.shapes.push(Instance::new(prim.clone(), model_to_world));
+                shape_obj_indices.push(obj_idx);
+                shape_material_indices.push(obj_material_idx);
             }
         }
+        mesh.obj_indices.append(&mut shape_obj_indices);
+        mesh.material_indices.append(&mut shape_material_indices);
+        mesh.b_boxes = mesh.triangles.iter()
+            .map(|t| t.get_bounding_box())
+            .chain(mesh.shapes.iter().map(|s| s.get_bounding_box()))
+            .collect();
     
-        let centroids: Vec<Centroid> = mesh.b_boxes.iter()
-            .enumerate()
-            .map(|(idx, &t)| (t.get_centroid(), idx))
+        let (bounded, unbounded): (Vec<usize>, Vec<usize>) = (0..mesh.b_boxes.len())
+            .partition(|&idx| mesh.b_boxes[idx].is_finite());
+        mesh.unbounded = unbounded;
+        let centroids: Vec<Centroid> = bounded.into_iter()
+            .map(|idx| (mesh.b_boxes[idx].get_centroid(), idx))
             .collect();
     
-        mesh.build_bvh(&centroids);
+        if !centroids.is_empty() {
+            mesh.build_bvh(&centroids);
+        }
         
         mesh.lights = self.lights.clone();
         mesh
@@ -80,38 +133,95 @@ type Centroid = (Point3d, usize);
 
 pub trait IntoTriangles {
 	fn triangulate(&self) -> Vec<Triangle>;
+	/// Materials of the model itself, e.g. from an MTL library
+	fn get_materials(&self) -> Vec<Arc<Material>> {
+		Vec::new()
+	}
+	/// Index into get_materials() for every triangle, empty if the model has no materials
+	fn get_material_indices(&self) -> Vec<Option<usize>> {
+		Vec::new()
+	}
 }
 
+/// A node of the scene graph. Its transform is relative to the parent node,
+/// so moving the parent moves all its children as well.
 pub struct SceneObj {
-	triangles: Vec<Triangle>,
+	name: String,
+	children: Vec<SceneObj>,
+	pub(crate) triangles: Vec<Triangle>,
+	/// Primitives other than triangles, e.g. spheres, in the model space
+	primitives: Vec<Arc<dyn TraceablePrimitive>>,
 	scale: [f32; 3],
-	rotation: [f32; 3],
+	rotation: Quaternion,
 	translation: [f32; 3],
+	double_sided: bool,
+	/// Applies to the triangles which have no material of their own, and to the other primitives
+	material: Material,
+	/// Materials of the model, e.g. from an MTL library
+	materials: Vec<Arc<Material>>,
+	/// Index into materials for every triangle
+	pub(crate) material_indices: Vec<Option<usize>>,
 	//model_to_world: Mat4f,
 	// world_to_model: Mat4f,
 }
 
 impl SceneObj {
-	pub(crate) fn new(a: &impl IntoTriangles) -> Self {
+	pub fn new(a: &impl IntoTriangles) -> Self {
+		let triangles = a.triangulate();
+		let mut material_indices = a.get_material_indices();
+		material_indices.resize(triangles.len(), None);
 		SceneObj {
-			triangles: a.triangulate(),
-			scale: [0.0, 0.0, 0.0],
-			rotation: [0.0, 0.0, 0.0],
+			triangles,
+			materials: a.get_materials(),
+			material_indices,
+			..SceneObj::group()
+		}
+	}
+	
+	/// An object made of a single primitive, which is traced exactly instead of being
+	/// tessellated. The rasterizer doesn't draw such primitives.
+	pub fn from_primitive(prim: impl TraceablePrimitive + 'static) -> Self {
+		SceneObj {
+			primitives: vec![Arc::new(prim)],
+			..SceneObj::group()
+		}
+	}
+	
+	/// A node without geometry, used to move its children together
+	pub fn group() -> Self {
+		SceneObj {
+			name: String::new(),
+			children: Vec::new(),
+			triangles: Vec::new(),
+			primitives: Vec::new(),
+			scale: [1.0, 1.0, 1.0],
+			rotation: Quaternion::identity(),
 			translation: [0.0, 0.0, 0.0],
+			double_sided: true,
+			material: Material::default(),
+			materials: Vec::new(),
+			material_indices: Vec::new(),
 		}
 	}
 	
-	fn set_model_mtx(&self) -> Mat4f {
+	pub(crate) fn set_model_mtx(&self) -> Mat4f {
 		Mat4f::identity()
 			.translate_xyz(&self.translation)
-			.rotate_about_x(self.rotation[0])
-			.rotate_about_y(self.rotation[1])
-			.rotate_about_z(self.rotation[2])
+			.rotate(&self.rotation)
 			.scale_xyz(&self.scale)
 	}
 
+	/// Euler angles in degrees, applied about X, then Y, then Z
 	pub fn rotate(mut self, x: f32, y: f32, z: f32) -> Self {
-		self.rotation = [x, y, z];
+		self.rotation = Quaternion::from_euler(x, y, z);
+		self
+	}
+	pub fn rotate_about_axis(mut self, axis: Vector3d, angle_deg: f32) -> Self {
+		self.rotation = Quaternion::from_axis_angle(&axis, angle_deg);
+		self
+	}
+	pub fn orientation(mut self, rotation: Quaternion) -> Self {
+		self.rotation = rotation;
 		self
 	}
 	pub fn scale(mut self, x: f32, y: f32, z: f32) -> Self {
@@ -122,15 +232,264 @@ impl SceneObj {
 		self.translation = [x, y, z];
 		self
 	}
-	pub fn apply_model_transform(&self) -> Vec<Triangle> {
-		let model_to_world = self.set_model_mtx();
-		self.triangles.iter().map(|t|
-			Triangle::new(
-				Point3d::from(&model_to_world * Point4d::from(t.v[0])),
-				Point3d::from(&model_to_world * Point4d::from(t.v[1])),
-				Point3d::from(&model_to_world * Point4d::from(t.v[2])),
-			)
-		).collect()
+	pub fn name(mut self, name: &str) -> Self {
+		self.name = name.to_string();
+		self
+	}
+	/// Single-sided triangles are culled when the ray hits their back side, which is cheaper
+	/// for closed meshes. Open meshes and thin surfaces need both sides.
+	pub fn double_sided(mut self, double_sided: bool) -> Self {
+		self.double_sided = double_sided;
+		self
+	}
+	pub fn material(mut self, material: Material) -> Self {
+		self.material = material;
+		self
+	}
+	pub fn add_child(mut self, child: SceneObj) -> Self {
+		self.children.push(child);
+		self
+	}
+	
+	pub fn get_name(&self) -> &str {
+		&self.name
+	}
+	pub fn get_children(&self) -> &[SceneObj] {
+		&self.children
+	}
+	pub fn get_material(&self) -> &Material {
+		&self.material
+	}
+	pub fn get_materials(&self) -> &[Arc<Material>] {
+		&self.materials
+	}
+	
+	// Setters for the objects which are already in the scene, e.g. for animation
+	pub fn set_rotation(&mut self, rotation: Quaternion) {
+		self.rotation = rotation;
+	}
+	pub fn set_scale(&mut self, x: f32, y: f32, z: f32) {
+		self.scale = [x, y, z];
+	}
+	pub fn set_translation(&mut self, x: f32, y: f32, z: f32) {
+		self.translation = [x, y, z];
+	}
+	
+	pub fn find(&self, name: &str) -> Option<&SceneObj> {
+		if self.name == name {
+			Some(self)
+		} else {
+			self.children.iter().find_map(|c| c.find(name))
+		}
+	}
+	pub fn find_mut(&mut self, name: &str) -> Option<&mut SceneObj> {
+		if self.name == name {
+			Some(self)
+		} else {
+			self.children.iter_mut().find_map(|c| c.find_mut(name))
+		}
+	}
+	
+	pub fn apply_model_transform(&self, model_to_world: &Mat4f) -> Vec<Triangle> {
+		let normal_mtx = model_to_world.get_normal_mtx();
+		self.triangles.iter()
+			.map(|t| t.transform(model_to_world, &normal_mtx).double_sided(self.double_sided))
+			.collect()
 	}
 }
 
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::geometry::{Point4d, Ray};
+	use crate::geometry::plane::Plane;
+	use crate::geometry::sphere::Sphere;
+	use crate::geometry::torus::Torus;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	fn get_obj_origin(scene: &Scene, name: &str) -> Point3d {
+		Point3d::from(&scene.get_model_to_world_mtx(name).unwrap() * Point4d::from_coords(0.0, 0.0, 0.0, 1.0))
+	}
+
+	fn create_arm() -> Scene {
+		let tri = TriObj::new(Triangle::new(
+			Point3d::from_coords(0.0, 0.0, 0.0),
+			Point3d::from_coords(1.0, 0.0, 0.0),
+			Point3d::from_coords(0.0, 1.0, 0.0),
+		));
+		Scene::new().add_obj(
+			SceneObj::group()
+				.name("shoulder")
+				.translate(10.0, 0.0, 0.0)
+				.rotate(0.0, 0.0, 90.0)
+				.add_child(
+					SceneObj::new(&tri)
+						.name("elbow")
+						.translate(2.0, 0.0, 0.0)
+						.add_child(SceneObj::new(&tri).name("hand").translate(3.0, 0.0, 0.0).scale(2.0, 2.0, 2.0))
+				)
+		)
+	}
+
+	#[test]
+	fn children_follow_parents() {
+		let scene = create_arm();
+		let hand = get_obj_origin(&scene, "hand");
+		assert!((hand.x - 10.0).abs() < 1e-5 && (hand.y - 5.0).abs() < 1e-5);
+
+		let mut scene = scene;
+		scene.find_obj_mut("shoulder").unwrap().set_translation(0.0, 0.0, -1.0);
+		let elbow = get_obj_origin(&scene, "elbow");
+		assert!(elbow.x.abs() < 1e-5 && (elbow.y - 2.0).abs() < 1e-5 && (elbow.z + 1.0).abs() < 1e-5);
+		let hand = get_obj_origin(&scene, "hand");
+		assert!(hand.x.abs() < 1e-5 && (hand.y - 5.0).abs() < 1e-5 && (hand.z + 1.0).abs() < 1e-5);
+	}
+
+	#[test]
+	fn flatten_to_mesh() {
+		let scene = create_arm();
+		let names: Vec<&str> = scene.flatten().iter().map(|(obj, _)| obj.get_name()).collect();
+		assert_eq!(names, vec!["shoulder", "elbow", "hand"]);
+		let mesh = scene.to_mesh();
+		assert_eq!(mesh.triangles.len(), 2);
+		// The hand is scaled twice, and then turned by the shoulder
+		let v = mesh.triangles[1].v[1];
+		assert!((v.x - 10.0).abs() < 1e-5 && (v.y - 7.0).abs() < 1e-5);
+		assert!(scene.get_world_to_model_mtx("hand").is_some());
+		assert!(scene.find_obj("foot").is_none());
+	}
+
+	#[test]
+	fn mesh_hit_record() {
+		let mesh = create_arm().to_mesh();
+		// The hand spans x in [8; 10] and y in [5; 7] at z = 0
+		let ray = Ray::new(Point3d::from_coords(9.5, 5.5, 10.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+		let hit = mesh.intersect(&ray).unwrap();
+		assert_eq!((hit.prim_idx, hit.obj_idx), (1, 2));
+		assert!((hit.distance - 10.0).abs() < 1e-5);
+		assert!((hit.point.x - 9.5).abs() < 1e-5 && (hit.point.y - 5.5).abs() < 1e-5 && hit.point.z.abs() < 1e-5);
+		assert!((hit.normal.z.abs() - 1.0).abs() < 1e-5);
+		let [w0, w1, w2] = hit.barycentric;
+		assert!((w0 - 0.5).abs() < 1e-5 && (w1 - 0.25).abs() < 1e-5 && (w2 - 0.25).abs() < 1e-5);
+		let miss = Ray::new(Point3d::from_coords(0.0, 0.0, 10.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+		assert!(mesh.intersect(&miss).is_none());
+	}
+
+	#[test]
+	fn spheres_next_to_triangles() {
+		let scene = create_arm()
+			.add_obj(SceneObj::from_primitive(Sphere::new(Point3d::new(), 1.0)).translate(0.0, 0.0, -5.0).scale(2.0, 2.0, 2.0));
+		let mesh = scene.to_mesh();
+		assert_eq!((mesh.triangles.len(), mesh.shapes.len(), mesh.b_boxes.len()), (2, 1, 3));
+		let ray = Ray::new(Point3d::from_coords(0.0, 0.0, 10.0), Vector3d::from_coords(0.0, 0.0, -1.0));
+		let hit = mesh.intersect(&ray).unwrap();
+		assert_eq!((hit.prim_idx, hit.obj_idx), (2, 3));
+		assert!((hit.distance - 13.0).abs() < 1e-5);
+		assert!((hit.normal.z - 1.0).abs() < 1e-5);
+	}
+
+	#[test]
+	fn materials_per_primitive() {
+		let scene = create_arm()
+			.add_obj(SceneObj::from_primitive(Sphere::new(Point3d::new(), 1.0)).material(Material::new().albedo(1.0, 0.0, 0.0)));
+		let mesh = scene.to_mesh();
+		// One for every object, the arm has no MTL materials
+		assert_eq!((mesh.materials.len(), &mesh.material_indices[..]), (4, &[1, 2, 3][..]));
+		let hit = mesh.intersect(&Ray::new(Point3d::from_coords(0.0, 0.0, 10.0), Vector3d::from_coords(0.0, 0.0, -1.0))).unwrap();
+		assert_eq!(mesh.get_albedo(&hit), [1.0, 0.0, 0.0]);
+		assert_eq!(mesh.get_material(&hit).shininess, Material::default().shininess);
+	}
+
+	#[test]
+	fn shadows() {
+		let ground = Plane::new(Point3d::new(), Vector3d::from_coords(0.0, 1.0, 0.0));
+		let mesh = Scene::new()
+			.add_obj(SceneObj::from_primitive(ground))
+			.add_obj(SceneObj::from_primitive(Sphere::new(Point3d::from_coords(0.0, 5.0, 0.0), 1.0)))
+			.to_mesh();
+		let down = Vector3d::from_coords(0.0, -1.0, 0.0);
+		assert!(mesh.is_occluded(&Ray::new(Point3d::from_coords(0.0, 10.0, 0.0), down)));
+		// Both the sphere and the ground are beyond the end of the ray
+		assert!(!mesh.is_occluded(&Ray::with_interval(Point3d::from_coords(0.0, 10.0, 0.0), down, 0.0, 3.0)));
+		assert!(!mesh.is_occluded(&Ray::new(Point3d::from_coords(0.0, 10.0, 0.0), -down)));
+
+		let light = Light::new(Point3d::from_coords(0.0, 10.0, 0.0), 1.0);
+		assert_eq!(mesh.get_light_visibility(Point3d::new(), Vector3d::from_coords(0.0, 1.0, 0.0), &light), 0.0);
+		assert_eq!(mesh.get_light_visibility(Point3d::from_coords(5.0, 0.0, 0.0), Vector3d::from_coords(0.0, 1.0, 0.0), &light), 1.0);
+		// The area light is larger than the sphere, so it's only partly hidden
+		let light = light.area(3.0, 64);
+		let visibility = mesh.get_light_visibility(Point3d::new(), Vector3d::from_coords(0.0, 1.0, 0.0), &light);
+		assert!(visibility > 0.2 && visibility < 0.8, "{}", visibility);
+	}
+
+	#[test]
+	fn bounded_reflections() {
+		let mirror = Material::new().albedo(0.0, 0.0, 0.0).ambient(0.0).reflectivity(1.0);
+		let glow = Material::new().albedo(0.0, 0.0, 0.0).ambient(0.0).emission(1.0, 0.0, 0.0);
+		let up = Vector3d::from_coords(0.0, 1.0, 0.0);
+		let mesh = Scene::new()
+			.add_obj(SceneObj::from_primitive(Plane::new(Point3d::new(), up)).material(mirror.clone()))
+			.add_obj(SceneObj::from_primitive(Plane::new(Point3d::from_coords(0.0, 10.0, 0.0), up)).material(mirror))
+			.add_obj(SceneObj::from_primitive(Sphere::new(Point3d::from_coords(4.0, 4.0, 0.0), 1.0)).material(glow))
+			.to_mesh();
+		let shader = |a, b, c, d: &[Light], e: &Material, f, g: &dyn Fn(&Light) -> f32| shading::phong(a, b, c, d, e, f, g);
+		// The glowing sphere is only seen in the lower mirror
+		let ray = Ray::new(Point3d::from_coords(-4.0, 4.0, 0.0), Vector3d::from_coords(1.0, -1.0, 0.0).normalize());
+		assert_eq!(mesh.cast_ray(&ray, &shader, 0), [0.0; 3]);
+		assert_eq!(mesh.cast_ray(&ray, &shader, 1), [1.0, 0.0, 0.0]);
+		// Bounces between the mirrors until the depth runs out
+		let ray = Ray::new(Point3d::from_coords(-4.0, 4.0, 0.0), -up);
+		assert_eq!(mesh.cast_ray(&ray, &shader, 100), [0.0; 3]);
+		// The perfect mirror is not shaded itself, only the sphere seen in it is
+		static NUM_SHADED: AtomicUsize = AtomicUsize::new(0);
+		let counting_shader = |a, b, c, d: &[Light], e: &Material, f, g: &dyn Fn(&Light) -> f32| {
+			NUM_SHADED.fetch_add(1, Ordering::Relaxed);
+			shading::phong(a, b, c, d, e, f, g)
+		};
+		let ray = Ray::new(Point3d::from_coords(-4.0, 4.0, 0.0), Vector3d::from_coords(1.0, -1.0, 0.0).normalize());
+		assert_eq!(mesh.cast_ray(&ray, &counting_shader, 1), [1.0, 0.0, 0.0]);
+		assert_eq!(NUM_SHADED.load(Ordering::Relaxed), 1);
+	}
+
+	#[test]
+	fn refraction() {
+		let glass = Material::new().albedo(0.0, 0.0, 0.0).ambient(0.0).transparency(1.0);
+		let glow = Material::new().albedo(0.0, 0.0, 0.0).ambient(0.0).emission(1.0, 1.0, 1.0);
+		let create_mesh = |glass: Material| Scene::new()
+			.add_obj(SceneObj::from_primitive(Sphere::new(Point3d::new(), 1.0)).material(glass))
+			.add_obj(SceneObj::from_primitive(Sphere::new(Point3d::from_coords(5.0, 0.0, 0.0), 1.0)).material(glow.clone()))
+			.to_mesh();
+		let shader = |a, b, c, d: &[Light], e: &Material, f, g: &dyn Fn(&Light) -> f32| shading::phong(a, b, c, d, e, f, g);
+		// Head-on through the middle, 4% is reflected off either side and the green is absorbed over the diameter
+		let mesh = create_mesh(glass.clone().absorption(0.0, 1.0, 0.0));
+		let ray = Ray::new(Point3d::from_coords(-5.0, 0.0, 0.0), Vector3d::from_coords(1.0, 0.0, 0.0));
+		let color = mesh.cast_ray(&ray, &shader, 3);
+		let transmitted = 0.96 * 0.96;
+		assert!((color[0] - transmitted).abs() < 2e-3 && (color[1] - transmitted * (-2f32).exp()).abs() < 2e-3, "{:?}", color);
+		// The glowing sphere is not seen through the glass without enough depth, only the background reflection is
+		assert!(mesh.cast_ray(&ray, &shader, 1).iter().all(|&c| c < 1e-3));
+		// From inside at a steep angle to the surface, the light can't get out of the glass
+		let ray = Ray::new(Point3d::from_coords(0.0, 0.9, 0.0), Vector3d::from_coords(1.0, 0.0, 0.0));
+		assert_eq!(create_mesh(glass.clone()).cast_ray(&ray, &shader, 5), [0.0; 3]);
+		// Without the bending, most of it passes through to the glowing sphere
+		let color = create_mesh(glass.ior(1.0)).cast_ray(&ray, &shader, 5);
+		assert!(color.iter().all(|&c| c > 0.9), "{:?}", color);
+	}
+
+	#[test]
+	fn unbounded_ground() {
+		let ground = Plane::new(Point3d::from_coords(0.0, -1.0, 0.0), Vector3d::from_coords(0.0, 1.0, 0.0));
+		let scene = Scene::new()
+			.add_obj(SceneObj::from_primitive(ground).rotate(0.0, 0.0, 10.0))
+			.add_obj(SceneObj::from_primitive(Torus::new(Point3d::new(), 3.0, 1.0)).translate(0.0, 5.0, 0.0));
+		let mesh = scene.to_mesh();
+		assert_eq!(mesh.unbounded, vec![0]);
+		let hit = mesh.intersect(&Ray::new(Point3d::from_coords(3.0, 20.0, 0.0), Vector3d::from_coords(0.0, -1.0, 0.0))).unwrap();
+		assert_eq!(hit.obj_idx, 1);
+		assert!((hit.distance - 14.0).abs() < 1e-4);
+		let hit = mesh.intersect(&Ray::new(Point3d::from_coords(0.0, 20.0, 0.0), Vector3d::from_coords(0.0, -1.0, 0.0))).unwrap();
+		assert_eq!(hit.obj_idx, 0);
+		// Tilted, so the ground is a bit farther than 1 below the origin
+		assert!((hit.distance - 20.0 - 1.0 / 10f32.to_radians().cos()).abs() < 1e-4);
+	}
+}
@@ -0,0 +1,380 @@
+use std::f32::consts::PI;
+
+use crate::geometry::{Mat4f, Point3d, Ray, Vector3d};
+use crate::sampler::{self, Sampler};
+
+#[derive(Copy, Clone)]
+pub enum Projection {
+	/// Pinhole camera with the vertical field of view set by Camera::fov
+	Perspective,
+	/// Parallel rays, the frame covers the given number of world units vertically
+	Orthographic { height: f32 },
+	/// Equidistant fisheye, the circle inscribed in the frame covers fov degrees
+	Fisheye { fov: f32 },
+	/// Full 360 by 180 degrees panorama, the center of the frame looks at the target
+	Equirectangular,
+}
+
+/// Like in OpenGL, in the camera space the camera sits in the origin, looks down -Z,
+/// and +Y is up.
+#[derive(Copy, Clone)]
+pub struct Camera {
+	position: Point3d,
+	target: Point3d,
+	up: Vector3d,
+	projection: Projection,
+	fov_vert: f32,
+	aperture_radius: f32,
+	focus_dist: f32,
+	samples_per_pixel: u32,
+	z_near: f32,
+	z_far: f32,
+	frame_width: u32,
+	frame_height: u32,
+	/// Right, up and backward, updated whenever the position, the target or the up vector change
+	basis: (Vector3d, Vector3d, Vector3d),
+}
+
+impl Camera {
+	pub fn new(frame_width: u32, frame_height: u32) -> Self {
+		let position = Point3d::new();
+		let target = Point3d::from_coords(0.0, 0.0, -1.0);
+		let up = Vector3d::from_coords(0.0, 1.0, 0.0);
+		Camera {
+			position,
+			target,
+			up,
+			projection: Projection::Perspective,
+			fov_vert: 35.0,
+			aperture_radius: 0.0,
+			focus_dist: 1.0,
+			samples_per_pixel: 1,
+			z_near: 0.1,
+			z_far: 1000.0,
+			frame_width,
+			frame_height,
+			basis: compute_basis(position, target, up),
+		}
+	}
+
+	pub fn position(mut self, x: f32, y: f32, z: f32) -> Self {
+		self.position = Point3d::from_coords(x, y, z);
+		self.basis = compute_basis(self.position, self.target, self.up);
+		self
+	}
+	pub fn look_at(mut self, x: f32, y: f32, z: f32) -> Self {
+		self.target = Point3d::from_coords(x, y, z);
+		self.basis = compute_basis(self.position, self.target, self.up);
+		self
+	}
+	/// Only the part across the view direction matters. If there is none, e.g. when looking
+	/// straight down with +Y up, another axis is taken.
+	pub fn up(mut self, x: f32, y: f32, z: f32) -> Self {
+		self.up = Vector3d::from_coords(x, y, z);
+		self.basis = compute_basis(self.position, self.target, self.up);
+		self
+	}
+	pub fn projection(mut self, projection: Projection) -> Self {
+		self.projection = projection;
+		self
+	}
+	/// Vertical field of view in degrees
+	pub fn fov(mut self, fov_vert: f32) -> Self {
+		self.fov_vert = fov_vert;
+		self
+	}
+	/// Turns the pinhole into a thin lens: objects at focus_dist from the camera are sharp,
+	/// the others get blurred the more the larger the aperture is. Zero radius is a pinhole.
+	pub fn lens(mut self, aperture_radius: f32, focus_dist: f32) -> Self {
+		self.aperture_radius = aperture_radius;
+		self.focus_dist = focus_dist;
+		self
+	}
+	/// More than one sample jitters the rays over the pixel area and over the lens
+	pub fn samples_per_pixel(mut self, samples_per_pixel: u32) -> Self {
+		self.samples_per_pixel = samples_per_pixel.max(1);
+		self
+	}
+	pub fn clip_planes(mut self, z_near: f32, z_far: f32) -> Self {
+		self.z_near = z_near;
+		self.z_far = z_far;
+		self
+	}
+
+	pub fn get_position(&self) -> Point3d {
+		self.position
+	}
+	pub fn get_aspect_ratio(&self) -> f32 {
+		self.frame_width as f32 / self.frame_height as f32
+	}
+
+	/// Right, up and backward unit vectors of the camera in the world space
+	fn get_basis(&self) -> (Vector3d, Vector3d, Vector3d) {
+		self.basis
+	}
+
+	/// World-to-camera transformation
+	pub fn get_view_mtx(&self) -> Mat4f {
+		let (r, u, b) = self.get_basis();
+		let pos = self.position - Point3d::new();
+		Mat4f::from_rows(
+			[r.x, r.y, r.z, -(r * pos)],
+			[u.x, u.y, u.z, -(u * pos)],
+			[b.x, b.y, b.z, -(b * pos)],
+			[0.0, 0.0, 0.0, 1.0],
+		)
+	}
+
+	/// Fisheye and equirectangular projections are not linear, so they can't be expressed
+	/// with a matrix. For them the perspective projection with the same vertical FOV is used.
+	pub fn get_projection_mtx(&self) -> Mat4f {
+		match self.projection {
+			Projection::Orthographic { height } =>
+				Mat4f::orthographic(height, self.get_aspect_ratio(), self.z_near, self.z_far),
+			_ => Mat4f::perspective(self.fov_vert, self.get_aspect_ratio(), self.z_near, self.z_far),
+		}
+	}
+
+	/// Returns the ray with the normalized direction going through the point
+	/// of the frame, where (0, 0) is the bottom-left corner of the bottom-left pixel,
+	/// so (x + 0.5, y + 0.5) is the center of the pixel.
+	/// Returns None if the projection doesn't cover this point, e.g. outside of the fisheye circle.
+	pub fn get_primary_ray(&self, x: f32, y: f32) -> Option<Ray> {
+		let (r, u, b) = self.get_basis();
+		let aspect_ratio = self.get_aspect_ratio();
+		// [-1; 1] across the frame
+		let ndc_x = 2.0 * x / self.frame_width as f32 - 1.0;
+		let ndc_y = 2.0 * y / self.frame_height as f32 - 1.0;
+		match self.projection {
+			Projection::Perspective => {
+				let fov_scaling_factor = (self.fov_vert / 2.0).to_radians().tan();
+				let dir = r * (ndc_x * fov_scaling_factor * aspect_ratio) + u * (ndc_y * fov_scaling_factor) - b;
+				Some(Ray::new(self.position, dir.normalize()))
+			}
+			Projection::Orthographic { height } => {
+				let orig = self.position + r * (ndc_x * height * 0.5 * aspect_ratio) + u * (ndc_y * height * 0.5);
+				Some(Ray::new(orig, -b))
+			}
+			Projection::Fisheye { fov } => {
+				// The circle touches the shorter side of the frame
+				let (circle_x, circle_y) = if aspect_ratio > 1.0 {
+					(ndc_x * aspect_ratio, ndc_y)
+				} else {
+					(ndc_x, ndc_y / aspect_ratio)
+				};
+				let dist = (circle_x * circle_x + circle_y * circle_y).sqrt();
+				if dist > 1.0 {
+					return None;
+				}
+				let theta = dist * (fov / 2.0).to_radians();
+				let phi = circle_y.atan2(circle_x);
+				let dir = r * (theta.sin() * phi.cos()) + u * (theta.sin() * phi.sin()) - b * theta.cos();
+				Some(Ray::new(self.position, dir.normalize()))
+			}
+			Projection::Equirectangular => {
+				let longitude = ndc_x * PI;
+				let latitude = ndc_y * PI / 2.0;
+				let dir = r * (latitude.cos() * longitude.sin()) + u * latitude.sin() - b * (latitude.cos() * longitude.cos());
+				Some(Ray::new(self.position, dir.normalize()))
+			}
+		}
+	}
+
+	/// Same as get_primary_ray, but the ray starts from the point (lens_u, lens_v) of the
+	/// [0; 1)^2 square mapped onto the lens, and goes through the point where the ray
+	/// from the center of the lens crosses the focal plane.
+	pub fn get_lens_ray(&self, x: f32, y: f32, lens_u: f32, lens_v: f32) -> Option<Ray> {
+		let ray = self.get_primary_ray(x, y)?;
+		if self.aperture_radius <= 0.0 {
+			return Some(ray);
+		}
+		let (orig, dir) = (ray.orig, ray.dir);
+		let (r, u, b) = self.get_basis();
+		let focus_pt = match self.projection {
+			Projection::Perspective | Projection::Orthographic { .. } =>
+				orig + dir * (self.focus_dist / -(dir * b)),
+			// Wide angle projections focus on a sphere around the camera
+			Projection::Fisheye { .. } | Projection::Equirectangular =>
+				orig + dir * self.focus_dist,
+		};
+		let (disk_x, disk_y) = sampler::square_to_disk(lens_u, lens_v);
+		let lens_pt = orig + r * (disk_x * self.aperture_radius) + u * (disk_y * self.aperture_radius);
+		Some(Ray::new(lens_pt, (focus_pt - lens_pt).normalize()))
+	}
+
+	/// Rays of all the samples of the pixel (x, y). A single sample goes through the center of the pixel.
+	pub fn get_pixel_rays(&self, x: u32, y: u32) -> Vec<Ray> {
+		let pix_idx = y * self.frame_width + x;
+		let mut pix_sampler = Sampler::new(pix_idx);
+		let mut lens_sampler = Sampler::new(pix_idx ^ 0x5bd1_e995);
+		(0..self.samples_per_pixel)
+			.filter_map(|_| {
+				let (dx, dy) = if self.samples_per_pixel == 1 {
+					(0.5, 0.5)
+				} else {
+					pix_sampler.next_2d()
+				};
+				let (lens_u, lens_v) = lens_sampler.next_2d();
+				self.get_lens_ray(x as f32 + dx, y as f32 + dy, lens_u, lens_v)
+			})
+			.collect()
+	}
+}
+
+/// Right, up and backward unit vectors of the camera at the position looking at the target
+fn compute_basis(position: Point3d, target: Point3d, up: Vector3d) -> (Vector3d, Vector3d, Vector3d) {
+	let backward = (position - target).normalize();
+	let mut right = up.crossprod(&backward);
+	if right * right <= 1e-10 * (up * up) {
+		// The up vector is along the view direction, any axis across it will do
+		let (x, y, z) = (backward.x.abs(), backward.y.abs(), backward.z.abs());
+		let axis = if x <= y && x <= z {
+			Vector3d::from_coords(1.0, 0.0, 0.0)
+		} else if y <= z {
+			Vector3d::from_coords(0.0, 1.0, 0.0)
+		} else {
+			Vector3d::from_coords(0.0, 0.0, 1.0)
+		};
+		right = backward.crossprod(&axis);
+	}
+	let right = right.normalize();
+	let up = backward.crossprod(&right);
+	(right, up, backward)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::geometry::Point4d;
+
+	fn assert_near(a: f32, b: f32) {
+		assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+	}
+
+	#[test]
+	fn central_ray_hits_target() {
+		let camera = Camera::new(200, 100)
+			.position(1.0, 2.0, 3.0)
+			.look_at(4.0, 2.0, -1.0);
+		let Ray { orig, dir, .. } = camera.get_primary_ray(100.0, 50.0).unwrap();
+		assert_near(orig.x, 1.0);
+		assert_near(dir.x, 0.6);
+		assert_near(dir.y, 0.0);
+		assert_near(dir.z, -0.8);
+	}
+
+	#[test]
+	fn corner_ray_matches_fov() {
+		let camera = Camera::new(200, 100).fov(90.0);
+		let dir = camera.get_primary_ray(200.0, 100.0).unwrap().dir;
+		let expected = Vector3d::from_coords(2.0, 1.0, -1.0).normalize();
+		assert_near(dir.x, expected.x);
+		assert_near(dir.y, expected.y);
+		assert_near(dir.z, expected.z);
+	}
+
+	#[test]
+	fn orthographic_rays_are_parallel() {
+		let camera = Camera::new(200, 100)
+			.position(0.0, 0.0, 10.0)
+			.projection(Projection::Orthographic { height: 4.0 });
+		let Ray { orig, dir, .. } = camera.get_primary_ray(0.0, 100.0).unwrap();
+		assert_near(orig.x, -4.0);
+		assert_near(orig.y, 2.0);
+		assert_near(orig.z, 10.0);
+		assert_near(dir.z, -1.0);
+		let Ray { orig, dir, .. } = camera.get_primary_ray(150.0, 25.0).unwrap();
+		assert_near(orig.x, 2.0);
+		assert_near(orig.y, -1.0);
+		assert_near(dir.z, -1.0);
+	}
+
+	#[test]
+	fn fisheye_rays() {
+		let camera = Camera::new(200, 100).projection(Projection::Fisheye { fov: 180.0 });
+		let dir = camera.get_primary_ray(100.0, 50.0).unwrap().dir;
+		assert_near(dir.z, -1.0);
+		// The edge of the circle looks sideways
+		let dir = camera.get_primary_ray(100.0, 100.0).unwrap().dir;
+		assert_near(dir.y, 1.0);
+		assert_near(dir.z, 0.0);
+		let dir = camera.get_primary_ray(75.0, 50.0).unwrap().dir;
+		assert_near(dir.x, -(PI / 4.0).sin());
+		assert_near(dir.z, -(PI / 4.0).cos());
+		assert!(camera.get_primary_ray(0.0, 0.0).is_none());
+	}
+
+	#[test]
+	fn equirectangular_rays() {
+		let camera = Camera::new(200, 100).projection(Projection::Equirectangular);
+		let dir = camera.get_primary_ray(100.0, 50.0).unwrap().dir;
+		assert_near(dir.z, -1.0);
+		let dir = camera.get_primary_ray(150.0, 50.0).unwrap().dir;
+		assert_near(dir.x, 1.0);
+		let dir = camera.get_primary_ray(0.0, 50.0).unwrap().dir;
+		assert_near(dir.z, 1.0);
+		let dir = camera.get_primary_ray(40.0, 100.0).unwrap().dir;
+		assert_near(dir.y, 1.0);
+	}
+
+	#[test]
+	fn thin_lens_rays_converge_on_focal_plane() {
+		let camera = Camera::new(100, 100)
+			.fov(90.0)
+			.lens(0.5, 10.0);
+		let center_dir = camera.get_primary_ray(70.0, 40.0).unwrap().dir;
+		let expected = Point3d::new() + center_dir * (10.0 / -center_dir.z);
+		for &(u, v) in &[(0.0, 0.0), (0.9, 0.1), (0.5, 0.5), (0.25, 0.75)] {
+			let Ray { orig, dir, .. } = camera.get_lens_ray(70.0, 40.0, u, v).unwrap();
+			assert!(orig.x * orig.x + orig.y * orig.y <= 0.25 + 1e-6);
+			assert_near(orig.z, 0.0);
+			let focus_pt = orig + dir * ((-10.0 - orig.z) / dir.z);
+			assert_near(focus_pt.x, expected.x);
+			assert_near(focus_pt.y, expected.y);
+		}
+	}
+
+	#[test]
+	fn pixel_rays() {
+		let camera = Camera::new(100, 100).samples_per_pixel(16);
+		let rays = camera.get_pixel_rays(10, 20);
+		assert_eq!(rays.len(), 16);
+		// Pinhole camera, so all rays start in the same point
+		assert!(rays.iter().all(|ray| ray.orig.x == 0.0 && ray.orig.y == 0.0));
+		let camera = Camera::new(100, 100);
+		let rays = camera.get_pixel_rays(50, 50);
+		assert_eq!(rays.len(), 1);
+		assert_near(rays[0].dir.x, Camera::new(100, 100).get_primary_ray(50.5, 50.5).unwrap().dir.x);
+	}
+
+	#[test]
+	fn up_along_view_direction() {
+		// Straight down with the default +Y up
+		let camera = Camera::new(100, 100)
+			.position(0.0, 5.0, 0.0)
+			.look_at(0.0, 0.0, 0.0);
+		let dir = camera.get_primary_ray(50.0, 50.0).unwrap().dir;
+		assert_near(dir.y, -1.0);
+		let corner_dir = camera.get_primary_ray(0.0, 0.0).unwrap().dir;
+		assert!(corner_dir.x.is_finite() && corner_dir.z.is_finite() && corner_dir.y < 0.0);
+		let p = &camera.get_view_mtx() * Point4d::from_coords(0.0, 0.0, 0.0, 1.0);
+		assert_near(p.x, 0.0);
+		assert_near(p.z, -5.0);
+	}
+
+	#[test]
+	fn view_mtx_moves_target_to_minus_z() {
+		let camera = Camera::new(100, 100)
+			.position(0.0, 5.0, 0.0)
+			.look_at(0.0, 0.0, 0.0)
+			.up(0.0, 0.0, -1.0);
+		let p = &camera.get_view_mtx() * Point4d::from_coords(0.0, 0.0, 0.0, 1.0);
+		assert_near(p.x, 0.0);
+		assert_near(p.y, 0.0);
+		assert_near(p.z, -5.0);
+		let p = &camera.get_view_mtx() * Point4d::from_coords(1.0, 0.0, -2.0, 1.0);
+		assert_near(p.x, 1.0);
+		assert_near(p.y, 2.0);
+		assert_near(p.z, -5.0);
+	}
+}
@@ -1,10 +1,18 @@
 //pub mod light {
-use crate::geometry::Point3d;
+use crate::geometry::{Point3d, Vector3d};
+use crate::sampler::{self, Sampler};
 
+/// A point light, or a spherical area light if the radius is not 0.
+/// Area lights cast soft shadows.
 #[derive(Copy, Clone)]
 pub struct Light {
     pub position: Point3d,
     pub intensity: f32,
+    /// Linear RGB, scaled by the intensity
+    pub color: [f32; 3],
+    pub radius: f32,
+    /// Number of the shadow rays towards the light, the more the smoother the penumbra
+    pub num_samples: u32,
 }
 
 impl Light {
@@ -12,7 +20,51 @@ impl Light {
         Light {
             position,
             intensity,
+            color: [1.0; 3],
+            radius: 0.0,
+            num_samples: 1,
         }
     }
+    pub fn color(mut self, r: f32, g: f32, b: f32) -> Self {
+        self.color = [r, g, b];
+        self
+    }
+    
+    pub fn area(mut self, radius: f32, num_samples: u32) -> Self {
+        self.radius = radius;
+        self.num_samples = num_samples.max(1);
+        self
+    }
+    
+    /// The color times the intensity
+    pub fn get_radiance(&self) -> [f32; 3] {
+        [self.color[0] * self.intensity, self.color[1] * self.intensity, self.color[2] * self.intensity]
+    }
+    
+    /// Points on the light to send the shadow rays to. The sphere looks like a disk from the
+    /// surface point, so the points are spread over the disk facing it.
+    pub fn get_sample_points(&self, surface_pt: &Point3d) -> Vec<Point3d> {
+        if self.radius == 0.0 {
+            return vec![self.position];
+        }
+        let to_surface = (*surface_pt - self.position).normalize();
+        let helper = if to_surface.x.abs() < 0.9 {
+            Vector3d::from_coords(1.0, 0.0, 0.0)
+        } else {
+            Vector3d::from_coords(0.0, 1.0, 0.0)
+        };
+        let u_axis = to_surface.crossprod(&helper).normalize() * self.radius;
+        let v_axis = to_surface.crossprod(&u_axis);
+        // Every surface point gets its own pattern, which turns the banding into noise
+        let seed = sampler::hash(surface_pt.x.to_bits() ^ sampler::hash(surface_pt.y.to_bits() ^ sampler::hash(surface_pt.z.to_bits())));
+        let mut light_sampler = Sampler::new(seed);
+        (0..self.num_samples)
+            .map(|_| {
+                let (u, v) = light_sampler.next_2d();
+                let (x, y) = sampler::square_to_disk(u, v);
+                self.position + u_axis * x + v_axis * y
+            })
+            .collect()
+    }
 }
 //}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use crate::scene::mtl::MtlMaterial;
+use crate::scene::texture::Texture;
+
+/// How a surface reflects the light, used by the shading at every hit
+#[derive(Clone)]
+pub struct Material {
+	/// Base color, in [0; 1]
+	pub albedo: [f32; 3],
+	/// Multiplies the albedo, looked up with the texture coordinates of the triangles
+	pub texture: Option<Arc<Texture>>,
+	/// Weights of the Phong terms
+	pub diffuse: f32,
+	pub specular: f32,
+	pub ambient: f32,
+	/// The exponent of the specular highlight, the larger the sharper
+	pub shininess: f32,
+	/// Fraction of the light coming from the mirror direction
+	pub reflectivity: f32,
+	/// Fraction of the light passing through the surface
+	pub transparency: f32,
+	/// Index of refraction of the medium behind the surface, e.g. 1.33 for water or 1.5 for glass
+	pub ior: f32,
+	/// Absorption coefficients of the medium, after the distance d inside it exp(-absorption * d)
+	/// of the light is left. Zero for clear media, the larger the deeper the tint of the thick parts.
+	pub absorption: [f32; 3],
+	/// Light given off by the surface itself, regardless of the lights
+	pub emission: [f32; 3],
+}
+
+impl Default for Material {
+	fn default() -> Self {
+		Material {
+			albedo: [1.0; 3],
+			texture: None,
+			diffuse: 1.0,
+			specular: 0.1,
+			ambient: 0.1,
+			shininess: 20.0,
+			reflectivity: 0.0,
+			transparency: 0.0,
+			ior: 1.5,
+			absorption: [0.0; 3],
+			emission: [0.0; 3],
+		}
+	}
+}
+
+impl Material {
+	pub fn new() -> Self {
+		Material::default()
+	}
+
+	pub fn albedo(mut self, r: f32, g: f32, b: f32) -> Self {
+		self.albedo = [r, g, b];
+		self
+	}
+	pub fn texture(mut self, texture: Arc<Texture>) -> Self {
+		self.texture = Some(texture);
+		self
+	}
+	pub fn diffuse(mut self, diffuse: f32) -> Self {
+		self.diffuse = diffuse;
+		self
+	}
+	pub fn specular(mut self, specular: f32) -> Self {
+		self.specular = specular;
+		self
+	}
+	pub fn ambient(mut self, ambient: f32) -> Self {
+		self.ambient = ambient;
+		self
+	}
+	pub fn shininess(mut self, shininess: f32) -> Self {
+		self.shininess = shininess;
+		self
+	}
+	pub fn reflectivity(mut self, reflectivity: f32) -> Self {
+		self.reflectivity = reflectivity;
+		self
+	}
+	pub fn transparency(mut self, transparency: f32) -> Self {
+		self.transparency = transparency;
+		self
+	}
+	pub fn ior(mut self, ior: f32) -> Self {
+		self.ior = ior;
+		self
+	}
+	pub fn absorption(mut self, r: f32, g: f32, b: f32) -> Self {
+		self.absorption = [r, g, b];
+		self
+	}
+	pub fn emission(mut self, r: f32, g: f32, b: f32) -> Self {
+		self.emission = [r, g, b];
+		self
+	}
+
+	/// The albedo times the texture at the texture coordinates
+	pub fn get_albedo(&self, u: f32, v: f32) -> [f32; 3] {
+		match &self.texture {
+			Some(texture) => {
+				let texel = texture.sample(u, v);
+				[self.albedo[0] * texel[0], self.albedo[1] * texel[1], self.albedo[2] * texel[2]]
+			}
+			None => self.albedo,
+		}
+	}
+}
+
+impl From<&MtlMaterial> for Material {
+	fn from(mtl: &MtlMaterial) -> Self {
+		let specular = (mtl.specular[0] + mtl.specular[1] + mtl.specular[2]) / 3.0;
+		Material {
+			albedo: mtl.diffuse,
+			texture: mtl.diffuse_map.clone(),
+			diffuse: 1.0,
+			// The illumination models 0 and 1 have no highlights
+			specular: if mtl.illum >= 2 { specular } else { 0.0 },
+			shininess: mtl.shininess,
+			// The models from 3 on trace the reflections, weighted by Ks
+			reflectivity: if mtl.illum >= 3 { specular } else { 0.0 },
+			transparency: 1.0 - mtl.dissolve,
+			ior: mtl.optical_density,
+			emission: mtl.emission,
+			..Material::default()
+		}
+	}
+}
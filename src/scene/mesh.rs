@@ -1,13 +1,28 @@
-use crate::geometry::{Point3d, TraceablePrimitive, Vector3d};
+use std::sync::Arc;
+
+use crate::geometry::{Hit, Instance, Point3d, Ray, TraceablePrimitive, Vector3d};
 use crate::geometry::aabb::Aabb;
 use crate::geometry::triangle::Triangle;
-use crate::scene::{Centroid};
+use crate::scene::{Centroid, BG_COLOR};
 use crate::scene::light::Light;
+use crate::scene::material::Material;
+use crate::scene::shading;
 //use crate::VtxShader;
 
+#[derive(Default)]
 pub struct Mesh {
     pub lights: Vec<Light>,
     pub triangles: Vec<Triangle>,
+    /// Non-triangle primitives, their indices follow the ones of the triangles
+    pub shapes: Vec<Instance>,
+    /// Primitives with infinite bounding boxes, e.g. planes, which are left out of the BVH
+    pub unbounded: Vec<usize>,
+    /// Materials of all the SceneObjs and of their models
+    pub materials: Vec<Arc<Material>>,
+    /// Index into materials for every primitive
+    pub material_indices: Vec<usize>,
+    /// Index of the SceneObj every primitive comes from, in the Scene::flatten() order
+    pub obj_indices: Vec<usize>,
     //vtx_normals: Vec<Vector3d>,
     //txt_coords: Vec<Point3d>,
     pub b_boxes: Vec<Aabb>,
@@ -17,16 +32,49 @@ pub struct Mesh {
     bvh_nodes: Vec<Node>,
 }
 
+/// Secondary rays start that far from the surface, relative to the magnitude of the coordinates,
+/// otherwise they hit the surface they start from
+const ORIGIN_OFFSET_SCALE: f32 = 1e-4;
+
+/// Moves the origin of a secondary ray off the surface, to the side where the ray goes
+fn offset_origin(surface_pt: Point3d, geom_normal: Vector3d, dir: Vector3d) -> Point3d {
+    let magnitude = surface_pt.x.abs().max(surface_pt.y.abs()).max(surface_pt.z.abs()).max(1.0);
+    let offset = geom_normal * (ORIGIN_OFFSET_SCALE * magnitude);
+    if dir * geom_normal < 0.0 {
+        surface_pt + -offset
+    } else {
+        surface_pt + offset
+    }
+}
+
 fn reflection_dir(surface_normal: Vector3d, surface_to_camera: Vector3d) -> Vector3d {
     let l2n_cos = surface_to_camera * surface_normal;
     surface_normal * l2n_cos * 2.0 - surface_to_camera
 }
 
+/// Direction of the ray passing through the surface by the Snell's law, along with the cos of
+/// the angle to the normal on the other side. None on the total internal reflection.
+/// The normal faces the incoming ray, eta is the ratio of the indices of refraction.
+fn refraction_dir(normal: Vector3d, dir: Vector3d, eta: f32) -> Option<(Vector3d, f32)> {
+    let cos_i = -(dir * normal);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t > 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(((dir * eta + normal * (eta * cos_i - cos_t)).normalize(), cos_t))
+}
+
 impl Mesh {
     pub fn new() -> Self {
         Mesh {
             lights: Vec::new(),
             triangles: Vec::new(),
+            shapes: Vec::new(),
+            unbounded: Vec::new(),
+            materials: Vec::new(),
+            material_indices: Vec::new(),
+            obj_indices: Vec::new(),
             //vtx_normals: Vec::new(),
             //txt_coords: Vec::new(),
             b_boxes: Vec::new(),
@@ -36,7 +84,7 @@ impl Mesh {
         }
     }
     
-    pub fn build_bvh(&mut self, v: &Vec<Centroid>) -> usize {
+    pub fn build_bvh(&mut self, v: &[Centroid]) -> usize {
         
         let (mut min, _) = v[0]; //TODO refactor
         let (mut max, _) = v[0]; //TODO refactor
@@ -65,11 +113,11 @@ impl Mesh {
             let mid_value = (min.x + max.x) / 2.0; //TODO refactor
             let (left, right): (Vec<Centroid>, Vec<Centroid>) = v.iter().partition(|&&(x, _)| x.x < mid_value);
             //std::mem::drop(v); //TODO is this needed for memory usage optimization?
-            if left.len() > 0 {
+            if !left.is_empty() {
                 let child0 = Mesh::build_bvh(self, &left);
                 self.bvh_nodes[node_idx].children.push(child0);
             }
-            if right.len() > 0 {
+            if !right.is_empty() {
                 let child1 = Mesh::build_bvh(self, &right);
                 self.bvh_nodes[node_idx].children.push(child1);
             }
@@ -79,83 +127,205 @@ impl Mesh {
         node_idx
     }
     
-    pub fn cast_ray<F>(&self, ray_orig: &Point3d, ray_dir: &Vector3d, vtx_shader: &F, depth: usize) -> [u8; 3]
-    where F: FnOnce(Point3d, Point3d, Vector3d, &Vec<Light>) -> f32 + Send + Copy + 'static
-    {
-        const BG_COLOR: [u8; 3] = [30u8; 3];
-        const DEPTH_THRESHOLD: usize = 0;
-        
-        if depth < DEPTH_THRESHOLD {
-            return BG_COLOR;
+    fn get_prim_distance(&self, prim_idx: usize, ray: &Ray) -> Option<f32> {
+        match self.triangles.get(prim_idx) {
+            Some(t) => t.get_distance_to(ray),
+            None => self.shapes[prim_idx - self.triangles.len()].get_distance_to(ray),
         }
+    }
+    
+    fn get_prim_hit(&self, prim_idx: usize, ray: &Ray) -> Option<Hit> {
+        match self.triangles.get(prim_idx) {
+            Some(t) => t.get_hit(ray),
+            None => self.shapes[prim_idx - self.triangles.len()].get_hit(ray),
+        }
+    }
+    
+    /// Triangles interpolate the normals of their vertices, the other primitives are smooth anyway
+    pub fn get_shading_normal(&self, hit: &Hit) -> Vector3d {
+        match self.triangles.get(hit.prim_idx) {
+            Some(t) => t.interpolate_normal(&hit.barycentric),
+            None => hit.normal,
+        }
+    }
+    
+    pub fn get_material(&self, hit: &Hit) -> &Material {
+        &self.materials[self.material_indices[hit.prim_idx]]
+    }
+    
+    /// Color of the material at the hit, including its texture
+    pub fn get_albedo(&self, hit: &Hit) -> [f32; 3] {
+        let material = self.get_material(hit);
+        match self.triangles.get(hit.prim_idx) {
+            Some(t) => {
+                let [u, v] = t.interpolate_tex_coords(&hit.barycentric);
+                material.get_albedo(u, v)
+            }
+            // The other primitives have no texture coordinates yet
+            None => material.albedo,
+        }
+    }
+    
+    /// The closest hit along the ray, if any
+    pub fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        // The ray gets shorter with every hit, so farther boxes and triangles are rejected early
+        let mut ray = *ray;
+        let mut nearest_hit: Option<Hit> = None;
+        let mut test_prim = |i: usize, ray: &mut Ray| {
+            if let Some(hit) = self.get_prim_hit(i, ray) {
+                ray.set_tmax(hit.distance);
+                nearest_hit = Some(Hit {
+                    prim_idx: i,
+                    obj_idx: self.obj_indices[i],
+                    ..hit
+                });
+            }
+        };
         
-        let mut distance_to_nearest_obj2 = f32::MAX;
-        let mut nearest_obj_idx2: Option<usize> = None;
-        
-        
-        
-        /*for (idx, triangle) in mesh.triangles.iter().enumerate() {
-			let distance_to_obj = triangle.get_distance_to(ray_orig, ray_dir);
-			match distance_to_obj {
-				Some(dist) if dist < distance_to_nearest_obj1 => {
-					distance_to_nearest_obj1 = dist;
-					nearest_obj_idx1 = Some(idx);
-				}
-				_ => (),
-			}
-		}*/
+        // Typically the ground, so testing it first culls a lot of the BVH
+        for &i in &self.unbounded {
+            test_prim(i, &mut ray);
+        }
         
         let mut node_stack: Vec<usize> = Vec::new();
-        node_stack.push(0);
-        while node_stack.len() > 0 {
-            let node_idx = node_stack.pop();
-            if node_idx == None {
-                break;
-            }
-            let node_idx = node_idx.unwrap();
+        if !self.bvh_nodes.is_empty() {
+            node_stack.push(0);
+        }
+        while let Some(node_idx) = node_stack.pop() {
             let current_node = &self.bvh_nodes[node_idx];
-            let distance_to_bb = current_node.bound.get_distance_to(ray_orig, ray_dir);
-            if distance_to_bb != None {
-                if current_node.children.len() == 0 {
-                    // we're in a leaf
-                    for &i in &current_node.pointers {
-                        let distance_to_obj = self.triangles[i].get_distance_to(ray_orig, ray_dir);
-                        match distance_to_obj {
-                            Some(dist) if dist < distance_to_nearest_obj2 => {
-                                distance_to_nearest_obj2 = dist;
-                                nearest_obj_idx2 = Some(i);
-                            }
-                            _ => (),
-                        }
-                    }
-                } else {
-                    current_node.children.iter()
-                        .for_each(|&x| node_stack.push(x));
+            if current_node.bound.get_distance_to(&ray).is_none() {
+                continue;
+            }
+            if current_node.children.is_empty() {
+                // we're in a leaf
+                for &i in &current_node.pointers {
+                    test_prim(i, &mut ray);
                 }
+            } else {
+                current_node.children.iter()
+                    .for_each(|&x| node_stack.push(x));
             }
         }
-        
-        // if nearest_obj_idx1 != nearest_obj_idx2 {
-        // 	assert_eq!(nearest_obj_idx1, nearest_obj_idx2);
-        // }
-        // if distance_to_nearest_obj1 != distance_to_nearest_obj2 {
-        // 	assert_eq!(distance_to_nearest_obj1, distance_to_nearest_obj2);
-        // }
-        
-        if let Some(idx) = nearest_obj_idx2 {
-            let surface_pt = (*ray_orig + *ray_dir) * distance_to_nearest_obj2;
-            let surface_normal: Vector3d = self.triangles[idx].get_normal(&surface_pt);
+        nearest_hit
+    }
     
-            let refl_dir = reflection_dir(surface_normal, -*ray_dir).normalize(); //TODO: normalize really needed?
-            
-            let refl_color = self.cast_ray(&surface_pt, &refl_dir, vtx_shader, depth + 1);
+    /// True if anything is hit within [tmin; tmax] of the ray. Stops at the first hit found,
+    /// which is cheaper than looking for the closest one, e.g. for the shadow rays.
+    pub fn is_occluded(&self, ray: &Ray) -> bool {
+        if self.unbounded.iter().any(|&i| self.get_prim_distance(i, ray).is_some()) {
+            return true;
+        }
+        let mut node_stack: Vec<usize> = Vec::new();
+        if !self.bvh_nodes.is_empty() {
+            node_stack.push(0);
+        }
+        while let Some(node_idx) = node_stack.pop() {
+            let current_node = &self.bvh_nodes[node_idx];
+            if current_node.bound.get_distance_to(ray).is_none() {
+                continue;
+            }
+            if current_node.pointers.iter().any(|&i| self.get_prim_distance(i, ray).is_some()) {
+                return true;
+            }
+            node_stack.extend_from_slice(&current_node.children);
+        }
+        false
+    }
+    
+    /// Fraction of the light reaching the surface point, 0 when the point is in the shadow.
+    /// Between 0 and 1 in the penumbra of the area lights.
+    /// The geometric normal is the one of the surface the point is on.
+    pub fn get_light_visibility(&self, surface_pt: Point3d, geom_normal: Vector3d, light: &Light) -> f32 {
+        let sample_points = light.get_sample_points(&surface_pt);
+        let num_visible = sample_points.iter()
+            .filter(|&&light_pt| {
+                let orig = offset_origin(surface_pt, geom_normal, light_pt - surface_pt);
+                let to_light = light_pt - orig;
+                let dist = (to_light * to_light).sqrt();
+                // The light itself is not in the mesh, so the ray ends at it
+                let shadow_ray = Ray::with_interval(orig, to_light * (1.0 / dist), 0.0, dist);
+                !self.is_occluded(&shadow_ray)
+            })
+            .count();
+        num_visible as f32 / sample_points.len() as f32
+    }
+    
+    /// Beer-Lambert absorption of the light coming from the hit, if the ray travels inside
+    /// the medium, i.e. hits the back face of a transparent material
+    fn absorb(&self, color: [f32; 3], material: &Material, hit: &Hit, ray: &Ray) -> [f32; 3] {
+        if ray.dir * hit.normal <= 0.0 || material.transparency <= 0.0 {
+            return color;
+        }
+        // The ray direction may be not normalized
+        let dist = hit.distance * (ray.dir * ray.dir).sqrt();
+        [
+            color[0] * (-material.absorption[0] * dist).exp(),
+            color[1] * (-material.absorption[1] * dist).exp(),
+            color[2] * (-material.absorption[2] * dist).exp(),
+        ]
+    }
+    
+    /// Linear RGB color seen along the ray, not limited to [0; 1].
+    /// depth is the number of the reflections left to follow.
+    pub fn cast_ray<F>(&self, ray: &Ray, vtx_shader: &F, depth: usize) -> [f32; 3]
+    where F: FnOnce(Point3d, Point3d, Vector3d, &[Light], &Material, [f32; 3], &dyn Fn(&Light) -> f32) -> [f32; 3] + Send + Copy + 'static
+    {
+        if let Some(hit) = self.intersect(ray) {
+            let surface_pt = hit.point;
+            let surface_normal = self.get_shading_normal(&hit);
+            let material = self.get_material(&hit);
             
-            let mut illumination = vtx_shader(surface_pt, *ray_orig, surface_normal, &self.lights);
-            if illumination > 1.0 {
-                illumination = 1.0
+            // Shading casts the shadow rays, so it's skipped for the surfaces with no color of their own
+            let shade = || vtx_shader(
+                surface_pt,
+                ray.orig,
+                surface_normal,
+                &self.lights,
+                material,
+                self.get_albedo(&hit),
+                &|light| self.get_light_visibility(surface_pt, hit.normal, light),
+            );
+            let (reflectivity, transparency) = (material.reflectivity, material.transparency);
+            if depth == 0 || (reflectivity <= 0.0 && transparency <= 0.0) {
+                return self.absorb(shade(), material, &hit, ray);
             }
-            let self_color = [(illumination * u8::MAX as f32) as u8; 3];
-            [refl_color[0] + self_color[0], 0, 0]
+            
+            let ray_dir = ray.dir.normalize();
+            let refl_dir = reflection_dir(surface_normal, -ray_dir).normalize(); //TODO: normalize really needed?
+            let cast_secondary = |dir: Vector3d| {
+                let secondary_ray = Ray::new(offset_origin(surface_pt, hit.normal, dir), dir);
+                self.cast_ray(&secondary_ray, vtx_shader, depth - 1)
+            };
+            
+            // Light passing through the surface, and the part of the light reflected off it
+            let (refr_color, fresnel) = if transparency > 0.0 {
+                // The geometric normal tells which side the ray comes from, the medium is behind the front face
+                let entering = ray_dir * hit.normal < 0.0;
+                let (n1, n2) = if entering { (1.0, material.ior) } else { (material.ior, 1.0) };
+                let facing_normal = if ray_dir * surface_normal < 0.0 { surface_normal } else { -surface_normal };
+                match refraction_dir(facing_normal, ray_dir, n1 / n2) {
+                    Some((refr_dir, cos_t)) => {
+                        let cos_i = -(ray_dir * facing_normal);
+                        let fresnel = shading::fresnel_schlick(if n1 <= n2 { cos_i } else { cos_t }, n1, n2);
+                        (cast_secondary(refr_dir), fresnel)
+                    }
+                    // Total internal reflection
+                    None => ([0.0; 3], 1.0),
+                }
+            } else {
+                ([0.0; 3], 0.0)
+            };
+            let refl_weight = (1.0 - transparency) * reflectivity + transparency * fresnel;
+            let refl_color = if refl_weight > 0.0 { cast_secondary(refl_dir) } else { [0.0; 3] };
+            let self_weight = (1.0 - transparency) * (1.0 - reflectivity);
+            let self_color = if self_weight > 0.0 { shade() } else { [0.0; 3] };
+            let refr_weight = transparency * (1.0 - fresnel);
+            let color = [
+                self_color[0] * self_weight + refl_color[0] * refl_weight + refr_color[0] * refr_weight,
+                self_color[1] * self_weight + refl_color[1] * refl_weight + refr_color[1] * refr_weight,
+                self_color[2] * self_weight + refl_color[2] * refl_weight + refr_color[2] * refr_weight,
+            ];
+            self.absorb(color, material, &hit, ray)
         } else {
             BG_COLOR
         }
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use wavefront_obj::ParseError;
+
+use crate::scene::texture::Texture;
+
+/// A material of a Wavefront .mtl library, applied to the faces following `usemtl <name>`.
+/// It is turned into a Material for the rendering.
+#[derive(Clone)]
+pub struct MtlMaterial {
+	pub name: String,
+	/// Kd
+	pub diffuse: [f32; 3],
+	/// Ks
+	pub specular: [f32; 3],
+	/// Ns, the exponent of the specular highlight
+	pub shininess: f32,
+	/// Ke
+	pub emission: [f32; 3],
+	/// d, 1 for opaque materials
+	pub dissolve: f32,
+	/// Ni, the index of refraction
+	pub optical_density: f32,
+	/// map_Kd, multiplies the diffuse color
+	pub diffuse_map: Option<Arc<Texture>>,
+	/// map_Bump, not used by the shading yet
+	pub bump_map: Option<Arc<Texture>>,
+	/// The illumination model, e.g. 2 for the highlights or 3 for the reflections
+	pub illum: u32,
+}
+
+impl MtlMaterial {
+	/// The defaults of the format, used for what the file leaves out
+	pub fn new(name: &str) -> Self {
+		MtlMaterial {
+			name: name.to_string(),
+			diffuse: [0.8; 3],
+			specular: [0.0; 3],
+			shininess: 0.0,
+			emission: [0.0; 3],
+			dissolve: 1.0,
+			optical_density: 1.0,
+			diffuse_map: None,
+			bump_map: None,
+			illum: 1,
+		}
+	}
+}
+
+/// A missing or unreadable file is reported as a ParseError at the line 0
+pub fn new_mtl_library(path: &str) -> Result<Vec<MtlMaterial>, ParseError> {
+	let mut file_content = String::new();
+	File::open(path)
+		.and_then(|mut f| f.read_to_string(&mut file_content))
+		.map_err(|err| ParseError {
+			line_number: 0,
+			message: format!("Failed to read {}: {}", path, err),
+		})?;
+	let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+	parse_mtl_library(&file_content, dir)
+}
+
+/// The parser of wavefront_obj expects the statements in a fixed order and doesn't know some
+/// of them, so the library is parsed here. Unknown statements are ignored. The texture paths
+/// are relative to dir.
+pub fn parse_mtl_library(content: &str, dir: &Path) -> Result<Vec<MtlMaterial>, ParseError> {
+	let mut materials: Vec<MtlMaterial> = Vec::new();
+	// Materials often share the same image
+	let mut textures: HashMap<String, Arc<Texture>> = HashMap::new();
+	for (line_idx, line) in content.lines().enumerate() {
+		let error = |message: String| ParseError {
+			line_number: line_idx + 1,
+			message,
+		};
+		let mut tokens = line.split_whitespace();
+		let statement = match tokens.next() {
+			Some(statement) if !statement.starts_with('#') => statement,
+			_ => continue,
+		};
+		let args: Vec<&str> = tokens.collect();
+		if statement == "newmtl" {
+			let name = args.first().ok_or_else(|| error("Expected the material name".to_string()))?;
+			materials.push(MtlMaterial::new(name));
+			continue;
+		}
+		let material = match materials.last_mut() {
+			Some(material) => material,
+			None => return Err(error(format!("Expected newmtl but got {}", statement))),
+		};
+		let mut load_map = || -> Result<Arc<Texture>, ParseError> {
+			// The options like "-bm 0.5" go before the file name
+			let file = args.last().ok_or_else(|| error("Expected the texture file".to_string()))?;
+			if let Some(texture) = textures.get(*file) {
+				return Ok(texture.clone());
+			}
+			let path = dir.join(file.replace('\\', "/"));
+			let texture = Texture::load(&path.to_string_lossy())
+				.map_err(|err| error(format!("Failed to load {}: {}", path.display(), err)))?;
+			let texture = Arc::new(texture);
+			textures.insert(file.to_string(), texture.clone());
+			Ok(texture)
+		};
+		match statement {
+			"Kd" => material.diffuse = parse_color(&args).map_err(error)?,
+			"Ks" => material.specular = parse_color(&args).map_err(error)?,
+			"Ke" => material.emission = parse_color(&args).map_err(error)?,
+			"Ns" => material.shininess = parse_float(&args).map_err(error)?,
+			"d" => material.dissolve = parse_float(&args).map_err(error)?,
+			// Transparency, the opposite of d
+			"Tr" => material.dissolve = 1.0 - parse_float(&args).map_err(error)?,
+			"Ni" => material.optical_density = parse_float(&args).map_err(error)?,
+			"illum" => material.illum = parse_float(&args).map_err(error)? as u32,
+			"map_Kd" => material.diffuse_map = Some(load_map()?),
+			"map_Bump" | "map_bump" | "bump" => material.bump_map = Some(load_map()?),
+			_ => (),
+		}
+	}
+	Ok(materials)
+}
+
+fn parse_float(args: &[&str]) -> Result<f32, String> {
+	let arg = args.first().ok_or_else(|| "Expected a number".to_string())?;
+	arg.parse().map_err(|_| format!("Expected a number but got {}", arg))
+}
+
+/// "r g b", or a single value for the gray colors
+fn parse_color(args: &[&str]) -> Result<[f32; 3], String> {
+	match args.len() {
+		1 => Ok([parse_float(args)?; 3]),
+		3 => Ok([parse_float(args)?, parse_float(&args[1..])?, parse_float(&args[2..])?]),
+		n => Err(format!("Expected 1 or 3 color values but got {}", n)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_library() {
+		let materials = parse_mtl_library("\
+# Exported
+newmtl red
+Kd 1.0 0.0 0.0
+Ks 0.5
+Ns 96.0
+illum 3
+
+newmtl glass
+Ni 1.5
+Tr 0.9
+Ke 0.1 0.2 0.3
+", Path::new("")).unwrap();
+		assert_eq!(materials.len(), 2);
+		let red = &materials[0];
+		assert_eq!((red.name.as_str(), red.diffuse, red.specular), ("red", [1.0, 0.0, 0.0], [0.5; 3]));
+		assert_eq!((red.shininess, red.illum, red.dissolve), (96.0, 3, 1.0));
+		let glass = &materials[1];
+		assert_eq!((glass.optical_density, glass.emission, glass.diffuse), (1.5, [0.1, 0.2, 0.3], [0.8; 3]));
+		assert!((glass.dissolve - 0.1).abs() < 1e-6);
+
+		let err = parse_mtl_library("newmtl a\nKd red\n", Path::new("")).err().unwrap();
+		assert_eq!(err.line_number, 2);
+		assert!(parse_mtl_library("Kd 1 1 1\n", Path::new("")).is_err());
+		let err = parse_mtl_library("newmtl a\nKs 0.5 0.5\n", Path::new("")).err().unwrap();
+		assert_eq!(err.line_number, 2);
+	}
+}
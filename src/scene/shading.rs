@@ -1,32 +1,93 @@
 use crate::geometry::{Point3d, Vector3d};
 use crate::scene::light::Light;
+use crate::scene::material::Material;
 
 
-pub(crate) fn phong(
+/// Linear RGB color of the surface point lit by the lights. The albedo is the one of the material
+/// at the point, i.e. with the texture applied. The highlights take the color of the lights.
+/// get_visibility tells the fraction of the light which is not blocked, it's only called for
+/// the lights in front of the surface.
+pub fn phong(
 	surface_pt: Point3d,
 	camera_pt: Point3d,
 	surface_normal: Vector3d,
-	lights: &Vec<Light>,
-) -> f32 {
-	let _shininess: f32 = 20.0; //TODO: use object material instead
-	let diffuse_reflection: f32 = 1.0; //TODO: use object material instead
-	let _specular_reflection: f32 = 0.1; //TODO: use object material instead
-	let ambient_reflection: f32 = 0.1; //TODO: use object material instead
-	
-	let _surface_to_camera = (camera_pt - surface_pt).normalize();
-	
-	let mut illumination = ambient_reflection;
+	lights: &[Light],
+	material: &Material,
+	albedo: [f32; 3],
+	get_visibility: &dyn Fn(&Light) -> f32,
+) -> [f32; 3] {
+	let surface_to_camera = (camera_pt - surface_pt).normalize();
+
+	let mut diffuse = [material.ambient; 3];
+	let mut specular = [0.0; 3];
 	for l in lights {
 		let surface_to_light = (l.position - surface_pt).normalize();
 		let diffuse_factor = surface_to_light * surface_normal; // cos of the light to normal angle
-		if diffuse_factor > 0.0 {
-			// let specular_factor = reflection_dir * surface_to_camera; // cos of the camera to reflected ray angle
-			// let mut specular_factor = specular_factor.powf(shininess);
-			// if specular_factor < 0.0 {
-			// 	specular_factor = 0.0;
-			// }
-			illumination +=	diffuse_factor * diffuse_reflection;// + specular_factor * specular_reflection;
+		if diffuse_factor <= 0.0 {
+			continue;
+		}
+		let visibility = get_visibility(l);
+		if visibility == 0.0 {
+			// In the shadow
+			continue;
+		}
+		let reflection_dir = surface_normal * diffuse_factor * 2.0 - surface_to_light;
+		let specular_factor = (reflection_dir * surface_to_camera).max(0.0); // cos of the camera to reflected ray angle
+		let specular_factor = specular_factor.powf(material.shininess) * material.specular;
+		let radiance = l.get_radiance();
+		for i in 0..3 {
+			diffuse[i] += radiance[i] * visibility * diffuse_factor * material.diffuse;
+			specular[i] += radiance[i] * visibility * specular_factor;
 		}
 	}
-	illumination
-}
\ No newline at end of file
+	[
+		albedo[0] * diffuse[0] + specular[0] + material.emission[0],
+		albedo[1] * diffuse[1] + specular[1] + material.emission[1],
+		albedo[2] * diffuse[2] + specular[2] + material.emission[2],
+	]
+}
+
+/// Fraction of the light reflected off the boundary between the media with the indices of
+/// refraction n1 and n2, the rest passes through. cos is the one of the angle to the normal
+/// on the side of the thinner medium. The Schlick's approximation of the Fresnel equations.
+pub fn fresnel_schlick(cos: f32, n1: f32, n2: f32) -> f32 {
+	let r0 = ((n1 - n2) / (n1 + n2)).powi(2);
+	r0 + (1.0 - r0) * (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn material_weights() {
+		let light = Light::new(Point3d::from_coords(0.0, 10.0, 0.0), 1.0);
+		let normal = Vector3d::from_coords(0.0, 1.0, 0.0);
+		let material = Material::new().albedo(1.0, 0.5, 0.0).ambient(0.2).diffuse(0.5).specular(0.0);
+		// Lit head-on, seen from the side
+		let color = phong(Point3d::new(), Point3d::from_coords(10.0, 0.0, 0.0), normal, &[light], &material, material.albedo, &|_| 1.0);
+		assert!((color[0] - 0.7).abs() < 1e-5 && (color[1] - 0.35).abs() < 1e-5 && color[2].abs() < 1e-5);
+		// Looking down the mirror direction, the highlight adds to every channel
+		let material = material.specular(0.25).emission(0.0, 0.0, 0.5);
+		let color = phong(Point3d::new(), Point3d::from_coords(0.0, 5.0, 0.0), normal, &[light], &material, material.albedo, &|_| 1.0);
+		assert!((color[0] - 0.95).abs() < 1e-5 && (color[1] - 0.6).abs() < 1e-5 && (color[2] - 0.75).abs() < 1e-5);
+		// Half as bright blue light
+		let light = light.color(0.0, 0.0, 1.0);
+		let light = Light { intensity: 0.5, ..light };
+		let color = phong(Point3d::new(), Point3d::from_coords(0.0, 5.0, 0.0), normal, &[light], &material, [1.0; 3], &|_| 1.0);
+		assert!((color[0] - 0.2).abs() < 1e-5 && (color[1] - 0.2).abs() < 1e-5 && (color[2] - 1.075).abs() < 1e-5);
+		// Only the ambient and the emission are left in the shadow
+		let color = phong(Point3d::new(), Point3d::from_coords(0.0, 5.0, 0.0), normal, &[light], &material, [1.0; 3], &|_| 0.0);
+		assert!((color[0] - 0.2).abs() < 1e-5 && (color[2] - 0.7).abs() < 1e-5);
+	}
+
+	#[test]
+	fn fresnel() {
+		// Head-on, glass reflects 4%
+		assert!((fresnel_schlick(1.0, 1.0, 1.5) - 0.04).abs() < 1e-5);
+		assert_eq!(fresnel_schlick(1.0, 1.5, 1.0), fresnel_schlick(1.0, 1.0, 1.5));
+		// Everything is reflected at grazing angles
+		assert_eq!(fresnel_schlick(0.0, 1.0, 1.5), 1.0);
+		assert!(fresnel_schlick(0.5, 1.0, 1.5) > 0.04);
+	}
+}
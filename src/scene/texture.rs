@@ -0,0 +1,133 @@
+use image::ImageResult;
+
+use crate::tone_mapping::decode_srgb;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter {
+	Nearest,
+	/// Blends the 4 texels around the sample point
+	Bilinear,
+}
+
+/// What happens to the texture coordinates outside of [0; 1]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Wrap {
+	Repeat,
+	Clamp,
+}
+
+/// Image sampled with the texture coordinates, where (0, 0) is the bottom-left corner
+/// of the image like in OpenGL and in the OBJ files
+pub struct Texture {
+	width: u32,
+	height: u32,
+	/// Rows go from the top to the bottom, the channels are linear and in [0; 1]
+	texels: Vec<[f32; 3]>,
+	filter: Filter,
+	wrap: Wrap,
+}
+
+impl Texture {
+	pub fn new(width: u32, height: u32, texels: Vec<[f32; 3]>) -> Self {
+		assert_eq!(texels.len(), (width * height) as usize);
+		Texture {
+			width,
+			height,
+			texels,
+			filter: Filter::Bilinear,
+			wrap: Wrap::Repeat,
+		}
+	}
+
+	/// The image is expected to be sRGB encoded, like the usual color maps
+	pub fn load(path: &str) -> ImageResult<Self> {
+		let img = image::open(path)?.to_rgb8();
+		let decode = |c: u8| decode_srgb(c as f32 / u8::MAX as f32);
+		let texels = img.pixels()
+			.map(|p| [decode(p[0]), decode(p[1]), decode(p[2])])
+			.collect();
+		Ok(Texture::new(img.width(), img.height(), texels))
+	}
+
+	pub fn filter(mut self, filter: Filter) -> Self {
+		self.filter = filter;
+		self
+	}
+	pub fn wrap(mut self, wrap: Wrap) -> Self {
+		self.wrap = wrap;
+		self
+	}
+
+	pub fn get_size(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	pub fn sample(&self, u: f32, v: f32) -> [f32; 3] {
+		// Texel centers are at the half-integer coordinates
+		let x = u * self.width as f32;
+		let y = (1.0 - v) * self.height as f32;
+		match self.filter {
+			Filter::Nearest => self.get_texel(x.floor() as i64, y.floor() as i64),
+			Filter::Bilinear => {
+				let (x, y) = (x - 0.5, y - 0.5);
+				let (x0, y0) = (x.floor(), y.floor());
+				let (fx, fy) = (x - x0, y - y0);
+				let (x0, y0) = (x0 as i64, y0 as i64);
+				let top = lerp(&self.get_texel(x0, y0), &self.get_texel(x0 + 1, y0), fx);
+				let bottom = lerp(&self.get_texel(x0, y0 + 1), &self.get_texel(x0 + 1, y0 + 1), fx);
+				lerp(&top, &bottom, fy)
+			}
+		}
+	}
+
+	fn get_texel(&self, x: i64, y: i64) -> [f32; 3] {
+		let (w, h) = (self.width as i64, self.height as i64);
+		let (x, y) = match self.wrap {
+			Wrap::Repeat => (x.rem_euclid(w), y.rem_euclid(h)),
+			Wrap::Clamp => (x.clamp(0, w - 1), y.clamp(0, h - 1)),
+		};
+		self.texels[(y * w + x) as usize]
+	}
+}
+
+fn lerp(a: &[f32; 3], b: &[f32; 3], t: f32) -> [f32; 3] {
+	[
+		a[0] + (b[0] - a[0]) * t,
+		a[1] + (b[1] - a[1]) * t,
+		a[2] + (b[2] - a[2]) * t,
+	]
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn checker() -> Texture {
+		// Black and white 2x2, white in the top-left and the bottom-right
+		Texture::new(2, 2, vec![[1.0; 3], [0.0; 3], [0.0; 3], [1.0; 3]])
+	}
+
+	#[test]
+	fn nearest() {
+		let tex = checker().filter(Filter::Nearest);
+		assert_eq!(tex.sample(0.25, 0.75), [1.0; 3]);
+		assert_eq!(tex.sample(0.75, 0.75), [0.0; 3]);
+		assert_eq!(tex.sample(0.75, 0.25), [1.0; 3]);
+		// Repeats
+		assert_eq!(tex.sample(-0.25, 1.75), [0.0; 3]);
+		let tex = tex.wrap(Wrap::Clamp);
+		assert_eq!(tex.sample(-0.25, 1.75), [1.0; 3]);
+	}
+
+	#[test]
+	fn bilinear() {
+		let tex = checker();
+		assert_eq!(tex.sample(0.25, 0.75), [1.0; 3]);
+		// Halfway between the texel centers
+		assert_eq!(tex.sample(0.5, 0.75), [0.5; 3]);
+		assert_eq!(tex.sample(0.5, 0.5), [0.5; 3]);
+		// The edge blends with the opposite side when repeating, but not when clamping
+		assert_eq!(tex.sample(0.0, 0.75), [0.5; 3]);
+		assert_eq!(tex.wrap(Wrap::Clamp).sample(0.0, 0.75), [1.0; 3]);
+	}
+}
@@ -13,9 +13,9 @@ impl TriObj {
 		}
 	}
 	
-	pub fn iter(&self) -> IterTriObj {
+	pub fn iter(&self) -> IterTriObj<'_> {
 		IterTriObj {
-			triobj: &self,
+			triobj: self,
 			idx: 0,
 		}
 	}
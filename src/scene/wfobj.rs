@@ -3,25 +3,71 @@ use wavefront_obj::ParseError;
 use std::sync::Arc;
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
+use std::convert::TryFrom;
 
 use crate::scene::IntoTriangles;
+use crate::scene::material::Material;
+use crate::scene::mtl::{self, MtlMaterial};
 use crate::geometry::triangle::Triangle;
-use crate::geometry::{Point3d};
+use crate::geometry::{Point3d, TraceablePrimitive, Vector3d};
 //use crate::geometry::matrix_transform::*;
 
+/// Faces meeting at a sharper angle keep the edge between them when the normals are computed
+const CREASE_ANGLE_COS: f32 = 0.5;
+
+/// Unit normal and the corner angle of every face around a vertex
+type VtxFaces = Vec<(Vector3d, f32)>;
+
 pub struct WfObj {
 	model: Arc<ObjSet>,
+	/// For every object and every vertex, used when the model doesn't ship normals
+	vtx_faces: Vec<Vec<VtxFaces>>,
+	/// Referred to by name from the face groups
+	materials: Vec<(String, Arc<Material>)>,
 }
 
 impl WfObj {
 	pub fn new(model: Arc<ObjSet>) -> Self {
+		let vtx_faces = model.objects.iter()
+			.map(get_vtx_faces)
+			.collect();
 		WfObj {
 			model,
+			vtx_faces,
+			materials: Vec::new(),
 		}
 	}
-	fn iter(&self) -> IterWfObj {
+	/// Loads the model along with the material library it refers to, if any.
+	/// The library path is relative to the model.
+	pub fn load(path: &str) -> Result<Self, ParseError> {
+		let model = new_wavefront_obj(path)?;
+		let materials = match &model.material_library {
+			Some(library) => {
+				let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+				mtl::new_mtl_library(&dir.join(library).to_string_lossy())?
+			}
+			None => Vec::new(),
+		};
+		Ok(WfObj::new(Arc::new(model)).materials(materials))
+	}
+	pub fn materials(mut self, materials: Vec<MtlMaterial>) -> Self {
+		self.materials = materials.iter()
+			.map(|mtl| (mtl.name.clone(), Arc::new(Material::from(mtl))))
+			.collect();
+		self
+	}
+	/// Number of the points and lines in the model, which are left out of the triangles
+	pub fn get_num_skipped(&self) -> usize {
+		self.model.objects.iter()
+			.flat_map(|object| object.geometry.iter())
+			.flat_map(|geometry| geometry.shapes.iter())
+			.filter(|shape| !matches!(shape.primitive, obj::Primitive::Triangle(..)))
+			.count()
+	}
+	fn iter(&self) -> IterWfObj<'_> {
 		IterWfObj {
-			wfobj: &self,
+			wfobj: self,
 			oidx: 0,
 			gidx: 0,
 			sidx: 0,
@@ -33,6 +79,22 @@ impl IntoTriangles for WfObj {
 	fn triangulate(&self) -> Vec<Triangle> {
 		self.iter().collect()
 	}
+	fn get_materials(&self) -> Vec<Arc<Material>> {
+		self.materials.iter().map(|(_, material)| material.clone()).collect()
+	}
+	/// The faces with an unknown material or without any get None
+	fn get_material_indices(&self) -> Vec<Option<usize>> {
+		self.model.objects.iter()
+			.flat_map(|object| object.geometry.iter())
+			.flat_map(|geometry| {
+				let material_idx = geometry.material_name.as_ref()
+					.and_then(|name| self.materials.iter().position(|(mtl_name, _)| mtl_name == name));
+				geometry.shapes.iter()
+					.filter(|shape| matches!(shape.primitive, obj::Primitive::Triangle(..)))
+					.map(move |_| material_idx)
+			})
+			.collect()
+	}
 }
 
 pub struct IterWfObj<'a> {
@@ -45,54 +107,392 @@ pub struct IterWfObj<'a> {
 impl<'a> Iterator for IterWfObj<'a> {
 	type Item = Triangle;
 	fn next(&mut self) -> Option<Self::Item> {
-		let object = self.wfobj.model.objects.get(self.oidx)?;
-		let geometry = object.geometry.get(self.gidx)?;
-		let shape = geometry.shapes.get(self.sidx)?;
-		
-		let coord_idx = match shape.primitive {
-			obj::Primitive::Triangle(
-					(coord_a, _a1, _a2),
-					(coord_b, _b1, _b2),
-					(coord_c, _c1, _c2)
-				) => {
-				//println!("IterObjSet {}:{}:{}", coord_a, coord_b, coord_c);
-				Some((coord_a, coord_b, coord_c))
+		loop {
+			let object = self.wfobj.model.objects.get(self.oidx)?;
+			let shape = object.geometry.get(self.gidx)
+				.and_then(|geometry| geometry.shapes.get(self.sidx));
+			let shape = match shape {
+				Some(shape) => shape,
+				None => {
+					// The end of the geometry or of the object
+					if self.gidx < object.geometry.len() {
+						self.gidx += 1;
+					} else {
+						self.gidx = 0;
+						self.oidx += 1;
+					}
+					self.sidx = 0;
+					continue;
+				}
+			};
+			self.sidx += 1;
+			
+			// Points and lines have no area to render, see WfObj::get_num_skipped
+			if let obj::Primitive::Triangle(a, b, c) = shape.primitive {
+				let corners = [a, b, c];
+				let v = [
+					get_position(object, a.0),
+					get_position(object, b.0),
+					get_position(object, c.0),
+				];
+				let triangle = Triangle::new(v[0], v[1], v[2]);
+				let face_normal = triangle.get_normal(&v[0]);
+				let vtx_faces = &self.wfobj.vtx_faces[self.oidx];
+				let mut vtx_normals = [face_normal; 3];
+				for (normal, &(vtx_idx, _, normal_idx)) in vtx_normals.iter_mut().zip(corners.iter()) {
+					*normal = match normal_idx {
+						Some(idx) => {
+							let n = object.normals[idx];
+							Vector3d::from_coords(n.x as f32, n.y as f32, n.z as f32).normalize()
+						}
+						None => get_smooth_normal(&vtx_faces[vtx_idx], &face_normal),
+					};
+				}
+				let mut tex_coords = [[0.0; 2]; 3];
+				for (uv, &(_, tex_idx, _)) in tex_coords.iter_mut().zip(corners.iter()) {
+					if let Some(idx) = tex_idx {
+						let t = object.tex_vertices[idx];
+						*uv = [t.u as f32, t.v as f32];
+					}
+				}
+				return Some(triangle.vertex_normals(vtx_normals).tex_coords(tex_coords));
 			}
-			_ => {
-				//println!("Unsupported primitive!");
-				None
-			}
-		}?;
-		
-		let a = object.vertices[coord_idx.0];
-		let b = object.vertices[coord_idx.1];
-		let c = object.vertices[coord_idx.2];
-		
-		self.sidx += 1;
-		if self.sidx >= geometry.shapes.len() {
-			self.sidx = 0;
-			self.gidx += 1;
 		}
-		if self.gidx >= object.geometry.len() {
-			self.gidx = 0;
-			self.oidx += 1;
+	}
+}
+
+fn get_position(object: &obj::Object, idx: usize) -> Point3d {
+	let v = object.vertices[idx];
+	Point3d::from_coords(v.x as f32, v.y as f32, v.z as f32)
+}
+
+/// Faces around every vertex of the object, empty if all the faces come with normals
+fn get_vtx_faces(object: &obj::Object) -> Vec<VtxFaces> {
+	let triangles: Vec<[usize; 3]> = object.geometry.iter()
+		.flat_map(|geometry| geometry.shapes.iter())
+		.filter_map(|shape| match shape.primitive {
+			obj::Primitive::Triangle(a, b, c) if a.2.is_none() || b.2.is_none() || c.2.is_none() =>
+				Some([a.0, b.0, c.0]),
+			_ => None,
+		})
+		.collect();
+	if triangles.is_empty() {
+		return Vec::new();
+	}
+	let mut vtx_faces = vec![Vec::new(); object.vertices.len()];
+	for idx in triangles {
+		let v = [get_position(object, idx[0]), get_position(object, idx[1]), get_position(object, idx[2])];
+		let normal = (v[1] - v[0]).crossprod(&(v[2] - v[0]));
+		if normal * normal == 0.0 {
+			// Degenerate
+			continue;
+		}
+		let normal = normal.normalize();
+		for corner in 0..3 {
+			let to_next = (v[(corner + 1) % 3] - v[corner]).normalize();
+			let to_prev = (v[(corner + 2) % 3] - v[corner]).normalize();
+			let angle = (to_next * to_prev).clamp(-1.0, 1.0).acos();
+			vtx_faces[idx[corner]].push((normal, angle));
 		}
-		
-		Some(Triangle::new(
-			Point3d::from_coords(a.x as f32, a.y as f32, a.z as f32),
-			Point3d::from_coords(b.x as f32, b.y as f32, b.z as f32),
-			Point3d::from_coords(c.x as f32, c.y as f32, c.z as f32),
-		))
 	}
+	vtx_faces
 }
 
+/// Normals of the faces around the vertex weighted by their corner angles, so the result
+/// doesn't depend on how the surface is split into triangles. The faces across a crease are left out.
+fn get_smooth_normal(vtx_faces: &[(Vector3d, f32)], face_normal: &Vector3d) -> Vector3d {
+	let sum = vtx_faces.iter()
+		.filter(|(normal, _)| *normal * *face_normal > CREASE_ANGLE_COS)
+		.fold(Vector3d::new(), |sum, &(normal, angle)| sum + normal * angle);
+	if sum * sum == 0.0 {
+		*face_normal
+	} else {
+		sum.normalize()
+	}
+}
 
+/// A missing or unreadable file is reported as a ParseError at the line 0
 pub fn new_wavefront_obj(path: &str) -> Result<ObjSet, ParseError> {
-	let file_content = {
-		let mut f = File::open(path).unwrap();
-		let mut content = String::new();
-		f.read_to_string(&mut content).unwrap();
-		content
+	let mut file_content = String::new();
+	File::open(path)
+		.and_then(|mut f| f.read_to_string(&mut file_content))
+		.map_err(|err| ParseError {
+			line_number: 0,
+			message: format!("Failed to read {}: {}", path, err),
+		})?;
+	parse_wavefront_obj(&file_content)
+}
+
+/// The parser turns polygons into triangle fans, which is wrong for the concave ones,
+/// so the polygons are triangulated by ear clipping before parsing
+pub fn parse_wavefront_obj(content: &str) -> Result<ObjSet, ParseError> {
+	let (content, line_numbers) = triangulate_polygons(content)?;
+	obj::parse(content).map_err(|err| {
+		// Back to the lines of the original content, past its end if the parser ran out of input
+		let last = line_numbers.last().copied().unwrap_or(0);
+		let line_number = line_numbers.get(err.line_number.wrapping_sub(1)).copied()
+			.unwrap_or_else(|| last + err.line_number.saturating_sub(line_numbers.len()));
+		ParseError { line_number, ..err }
+	})
+}
+
+/// Replaces every face with more than 3 corners with the triangles covering it.
+/// Also returns the number of the original line of every line of the result.
+fn triangulate_polygons(content: &str) -> Result<(String, Vec<usize>), ParseError> {
+	let mut vertices: Vec<Point3d> = Vec::new();
+	let mut res = String::with_capacity(content.len());
+	let mut line_numbers = Vec::new();
+	for (line_idx, line) in content.lines().enumerate() {
+		let mut tokens = line.split_whitespace();
+		match tokens.next() {
+			Some("v") => {
+				let coords = tokens.take(3)
+					.map(|t| t.parse().map_err(|_| ParseError {
+						line_number: line_idx + 1,
+						message: format!("Expected a number but got {}", t),
+					}))
+					.collect::<Result<Vec<f32>, ParseError>>()?;
+				// Missing coordinates are left for the parser to report
+				if coords.len() == 3 {
+					vertices.push(Point3d::from_coords(coords[0], coords[1], coords[2]));
+				}
+			}
+			Some("f") => {
+				let corners: Vec<&str> = tokens.collect();
+				if corners.len() > 3 {
+					let polygon: Option<Vec<Point3d>> = corners.iter()
+						.map(|corner| get_vertex(&vertices, corner))
+						.collect();
+					// Bad indices are left for the parser to report
+					if let Some(polygon) = polygon {
+						for [a, b, c] in ear_clip(&polygon) {
+							res.push_str(&format!("f {} {} {}\n", corners[a], corners[b], corners[c]));
+							line_numbers.push(line_idx + 1);
+						}
+						continue;
+					}
+				}
+			}
+			_ => (),
+		}
+		res.push_str(line);
+		res.push('\n');
+		line_numbers.push(line_idx + 1);
+	}
+	Ok((res, line_numbers))
+}
+
+/// Position of the corner like "7/2/5", the indices are 1-based or negative, i.e. relative to the end
+fn get_vertex(vertices: &[Point3d], corner: &str) -> Option<Point3d> {
+	let idx: isize = corner.split('/').next()?.parse().ok()?;
+	let idx = if idx < 0 { vertices.len() as isize + idx } else { idx - 1 };
+	vertices.get(usize::try_from(idx).ok()?).copied()
+}
+
+/// Splits a simple polygon, convex or not, into triangles with the same winding.
+/// Returns the indices of the corners.
+pub fn ear_clip(polygon: &[Point3d]) -> Vec<[usize; 3]> {
+	// Project onto the coordinate plane where the polygon is the largest
+	let mut normal = Vector3d::new();
+	for (i, a) in polygon.iter().enumerate() {
+		let b = polygon[(i + 1) % polygon.len()];
+		normal.x += (a.y - b.y) * (a.z + b.z);
+		normal.y += (a.z - b.z) * (a.x + b.x);
+		normal.z += (a.x - b.x) * (a.y + b.y);
+	}
+	let (u_axis, v_axis, orientation) = if normal.x.abs() > normal.y.abs() && normal.x.abs() > normal.z.abs() {
+		(1, 2, normal.x)
+	} else if normal.y.abs() > normal.z.abs() {
+		(2, 0, normal.y)
+	} else {
+		(0, 1, normal.z)
+	};
+	let pts: Vec<(f32, f32)> = polygon.iter().map(|p| (p[u_axis], p[v_axis])).collect();
+	// Positive for the corners turning the same way as the polygon does
+	let turn = |a: usize, b: usize, c: usize| {
+		let (a, b, c) = (pts[a], pts[b], pts[c]);
+		((b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)) * orientation.signum()
 	};
-	obj::parse(file_content)
+	
+	let mut res = Vec::with_capacity(polygon.len().saturating_sub(2));
+	let mut remaining: Vec<usize> = (0..polygon.len()).collect();
+	while remaining.len() > 3 {
+		let n = remaining.len();
+		let ear = (0..n).find(|&i| {
+			let (a, b, c) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+			turn(a, b, c) > 0.0 && remaining.iter()
+				.filter(|&&p| p != a && p != b && p != c)
+				.all(|&p| turn(a, b, p) < 0.0 || turn(b, c, p) < 0.0 || turn(c, a, p) < 0.0)
+		});
+		match ear {
+			Some(i) => {
+				res.push([remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]]);
+				remaining.remove(i);
+			}
+			None => {
+				// Degenerate or self-intersecting polygons have no ears, the rest becomes a fan
+				res.extend(remaining.windows(2).skip(1).map(|w| [remaining[0], w[0], w[1]]));
+				return res;
+			}
+		}
+	}
+	if remaining.len() == 3 {
+		res.push([remaining[0], remaining[1], remaining[2]]);
+	}
+	res
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn get_area(polygon: &[Point3d], triangles: &[[usize; 3]]) -> f32 {
+		triangles.iter()
+			.map(|&[a, b, c]| (polygon[b] - polygon[a]).crossprod(&(polygon[c] - polygon[a])).z * 0.5)
+			.sum()
+	}
+
+	#[test]
+	fn concave_polygon() {
+		// L-shaped, the fan from any corner but the inner one would cover the notch
+		let polygon: Vec<Point3d> = [(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0), (1.0, 2.0), (0.0, 2.0)].iter()
+			.map(|&(x, y)| Point3d::from_coords(x, y, 0.0))
+			.collect();
+		let triangles = ear_clip(&polygon);
+		assert_eq!(triangles.len(), 4);
+		assert!((get_area(&polygon, &triangles) - 3.0).abs() < 1e-5);
+		// The same winding as the polygon, so no triangle has a negative area
+		assert!(triangles.iter().all(|t| get_area(&polygon, &[*t]) > 0.0));
+		let reversed: Vec<Point3d> = polygon.iter().rev().copied().collect();
+		assert!((get_area(&reversed, &ear_clip(&reversed)) + 3.0).abs() < 1e-5);
+	}
+
+	#[test]
+	fn collinear_polygon() {
+		// All on a line, so there is no ear
+		let polygon: Vec<Point3d> = (0..5).map(|i| Point3d::from_coords(i as f32, 0.0, 0.0)).collect();
+		let triangles = ear_clip(&polygon);
+		assert_eq!(triangles, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+		// Collinear corners along an edge of a square are still covered
+		let polygon: Vec<Point3d> = [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)].iter()
+			.map(|&(x, y)| Point3d::from_coords(x, y, 0.0))
+			.collect();
+		let triangles = ear_clip(&polygon);
+		assert_eq!(triangles.len(), 3);
+		assert!((get_area(&polygon, &triangles) - 4.0).abs() < 1e-5);
+	}
+
+	#[test]
+	fn polygons_points_and_lines() {
+		let model = parse_wavefront_obj("\
+v 0 0 0
+v 2 0 0
+v 2 1 0
+v 1 1 0
+v 1 2 0
+v 0 2 0
+f 1 2 3 4 5 6
+l 1 2
+f 4 5 6
+").unwrap();
+		let wfobj = WfObj::new(Arc::new(model));
+		// The line does not end the iteration, so the last triangle is there too
+		assert_eq!(wfobj.triangulate().len(), 5);
+		assert_eq!(wfobj.get_num_skipped(), 1);
+	}
+
+	#[test]
+	fn vertex_normals() {
+		// A pyramid with the flat bottom and without normals, and a triangle with them
+		let model = parse_wavefront_obj("\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v -1 0 0
+v 0 -1 0
+v 0 0 0.2
+vn 0 0 2
+vt 0.5 0.25
+f 2 5 4 3
+f 6 2 3
+f 6 3 4
+f 6 4 5
+f 6 5 2
+f 1/1/1 2//1 3/1/1
+").unwrap();
+		let triangles = WfObj::new(Arc::new(model)).triangulate();
+		assert_eq!(triangles.len(), 7);
+		// The bottom is at more than the crease angle to the sides, so it stays flat
+		for t in &triangles[..2] {
+			assert!(t.get_vertex_normals().iter().all(|n| (n.z + 1.0).abs() < 1e-5));
+		}
+		for t in &triangles[2..6] {
+			for (v, n) in t.v.iter().zip(t.get_vertex_normals().iter()) {
+				if v.z > 0.0 {
+					// The apex is shared by 4 symmetric sides
+					assert!(n.x.abs() < 1e-5 && n.y.abs() < 1e-5 && (n.z - 1.0).abs() < 1e-5);
+				} else {
+					// Between the two sides at the base
+					assert!((n.x - v.x * n.z * 0.2).abs() < 1e-5 && (n.y - v.y * n.z * 0.2).abs() < 1e-5);
+				}
+			}
+		}
+		assert!(triangles[6].get_vertex_normals().iter().all(|n| (n.z - 1.0).abs() < 1e-5));
+		// The vertex without texture coordinates gets (0, 0)
+		let uv = triangles[6].interpolate_tex_coords(&[1.0 / 3.0; 3]);
+		assert!((uv[0] - 1.0 / 3.0).abs() < 1e-5 && (uv[1] - 0.5 / 3.0).abs() < 1e-5);
+	}
+
+	#[test]
+	fn parse_error_lines() {
+		let quad = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+		// The quad is split into two faces, which must not shift the following lines
+		let err = parse_wavefront_obj(&format!("{}f 1 2 9\n", quad)).err().unwrap();
+		assert_eq!(err.line_number, 6);
+		let err = parse_wavefront_obj(&format!("{}v 0 zero 0\n", quad)).err().unwrap();
+		assert_eq!(err.line_number, 6);
+		assert!(err.message.contains("zero"));
+	}
+
+	#[test]
+	fn missing_material_library() {
+		let path = std::env::temp_dir().join("pixodel_missing_mtllib.obj");
+		std::fs::write(&path, "mtllib pixodel_no_such_file.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+		let res = WfObj::load(&path.to_string_lossy());
+		std::fs::remove_file(&path).unwrap();
+		let err = res.err().unwrap();
+		assert_eq!(err.line_number, 0);
+		assert!(err.message.contains("pixodel_no_such_file.mtl"));
+	}
+
+	#[test]
+	fn missing_obj() {
+		let path = std::env::temp_dir().join("pixodel_no_such_file.obj");
+		let err = WfObj::load(&path.to_string_lossy()).err().unwrap();
+		assert_eq!(err.line_number, 0);
+		assert!(err.message.contains("pixodel_no_such_file.obj"));
+	}
+
+	#[test]
+	fn face_group_materials() {
+		let model = parse_wavefront_obj("\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+f 1 2 3
+usemtl red
+f 1 2 3 4
+l 1 3
+usemtl missing
+f 1 3 4
+usemtl blue
+f 1 3 4
+").unwrap();
+		let materials = mtl::parse_mtl_library("newmtl blue\nKd 0 0 1\nnewmtl red\nKd 1 0 0\n", Path::new("")).unwrap();
+		let wfobj = WfObj::new(Arc::new(model)).materials(materials);
+		assert_eq!(wfobj.get_material_indices(), vec![None, Some(1), Some(1), None, Some(0)]);
+		assert_eq!(wfobj.get_materials()[1].albedo, [1.0, 0.0, 0.0]);
+		assert_eq!(wfobj.triangulate().len(), 5);
+	}
 }
@@ -0,0 +1,114 @@
+//! The output stage, which turns the linear HDR colors of the renderers into 8-bit sRGB
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Operator {
+	/// Cuts off everything above 1
+	Clamp,
+	/// c / (1 + c), compresses the highlights but never reaches white
+	Reinhard,
+	/// Narkowicz's fit of the ACES filmic curve, with a toe in the shadows and a soft shoulder
+	Aces,
+}
+
+#[derive(Copy, Clone)]
+pub struct ToneMapper {
+	/// In stops, every one doubles the brightness
+	exposure: f32,
+	operator: Operator,
+}
+
+impl Default for ToneMapper {
+	fn default() -> Self {
+		ToneMapper {
+			exposure: 0.0,
+			operator: Operator::Clamp,
+		}
+	}
+}
+
+impl ToneMapper {
+	pub fn new() -> Self {
+		ToneMapper::default()
+	}
+
+	pub fn exposure(mut self, exposure: f32) -> Self {
+		self.exposure = exposure;
+		self
+	}
+	pub fn operator(mut self, operator: Operator) -> Self {
+		self.operator = operator;
+		self
+	}
+
+	/// Maps the linear color into [0; 1], still linear
+	pub fn tone_map(&self, color: &[f32; 3]) -> [f32; 3] {
+		let scale = self.exposure.exp2();
+		let mut res = [0.0; 3];
+		for (r, &c) in res.iter_mut().zip(color.iter()) {
+			let c = (c * scale).max(0.0);
+			*r = match self.operator {
+				Operator::Clamp => c,
+				Operator::Reinhard => c / (1.0 + c),
+				Operator::Aces => (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14),
+			}
+			.min(1.0);
+		}
+		res
+	}
+
+	/// The sRGB encoded pixel, ready to be saved
+	pub fn get_rgb8(&self, color: &[f32; 3]) -> [u8; 3] {
+		let color = self.tone_map(color);
+		let quantize = |c: f32| (encode_srgb(c) * u8::MAX as f32).round() as u8;
+		[quantize(color[0]), quantize(color[1]), quantize(color[2])]
+	}
+
+	/// Interleaved RGB bytes of the frame buffer, e.g. for image::ImageBuffer
+	pub fn get_rgb8_frame(&self, fbuf: &[[f32; 3]]) -> Vec<u8> {
+		fbuf.iter().flat_map(|color| self.get_rgb8(color)).collect()
+	}
+}
+
+/// The sRGB transfer function of the linear value in [0; 1]
+pub fn encode_srgb(c: f32) -> f32 {
+	if c <= 0.003_130_8 {
+		c * 12.92
+	} else {
+		1.055 * c.powf(1.0 / 2.4) - 0.055
+	}
+}
+
+/// The inverse of encode_srgb, e.g. for the colors of the images
+pub fn decode_srgb(c: f32) -> f32 {
+	if c <= 0.040_45 {
+		c / 12.92
+	} else {
+		((c + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn operators() {
+		let clamp = ToneMapper::new();
+		assert_eq!(clamp.tone_map(&[0.25, 1.0, 4.0]), [0.25, 1.0, 1.0]);
+		assert_eq!(clamp.exposure(1.0).tone_map(&[0.25, 1.0, -1.0]), [0.5, 1.0, 0.0]);
+		assert_eq!(clamp.operator(Operator::Reinhard).tone_map(&[1.0, 3.0, 0.0]), [0.5, 0.75, 0.0]);
+		let aces = clamp.operator(Operator::Aces).tone_map(&[0.0, 1.0, 100.0]);
+		assert!(aces[0] == 0.0 && (aces[1] - 0.8038).abs() < 1e-3 && aces[2] > 0.99 && aces[2] <= 1.0);
+	}
+
+	#[test]
+	fn srgb_encoding() {
+		let tone_mapper = ToneMapper::new();
+		assert_eq!(tone_mapper.get_rgb8(&[0.0, 1.0, 2.0]), [0, 255, 255]);
+		// The mid gray of the linear space is much brighter than the half of 255
+		assert_eq!(tone_mapper.get_rgb8(&[0.5; 3]), [188; 3]);
+		for &c in &[0.001, 0.2, 0.9] {
+			assert!((decode_srgb(encode_srgb(c)) - c).abs() < 1e-5);
+		}
+	}
+}
@@ -1,48 +1,134 @@
-// type VtxShader = Box<dyn FnOnce() + Send + 'static>;
-// type PixShader = Box<dyn FnOnce() + Send + 'static>;
-//
-//
-// struct Varying {
-// 	data: Vec<f32>,
-// }
-// impl Varying<T> {
-// 	fn push(self, val: T) {
-// 		self.data.push(val);
-// 	}
-// 	fn pop(self, val: T) {
-// 		self.data.pop(val);
-// 	}
-// 	fn interpolate(self) {
-//
-// 	}
-// }
-//
-// struct UnifiedShader {
-// 	view: Mat4f,
-// 	perspective_proj: Mat4f,
-// 	ortho_proj: Mat4f,
-// 	trianglepshaderdata:
-// }
-//
-//
-//
-// impl UnifiedShader {
-// 	fn new() -> UnifiedShader {
-// 		UnifiedShader {
-// 			view: Fmat4::identity(),
-// 			perspective_proj: Fmat4::identity(),
-// 			ortho_proj: Fmat4::identity(),
-// 		}
-// 	}
-//
-// 	fn run(self, vtx_shader: VtxShader, pix_shader: PixShader) {
-// 		loop {
-// 			vtx_shader();
-// 			break;
-// 		}
-// 		loop {
-// 			pix_shader();
-// 			break;
-// 		}
-// 	}
-// }
\ No newline at end of file
+use crate::geometry::{Mat4f, Point3d, Point4d, Vector3d};
+use crate::scene::light::Light;
+use crate::scene::material::Material;
+use crate::scene::shading;
+
+/// Values which stay the same for all the vertices and fragments of an object
+pub struct Uniforms<'a> {
+	pub model: Mat4f,
+	/// Inverse transpose of the model matrix, for transforming normals
+	pub model_normal: Mat4f,
+	pub view: Mat4f,
+	pub projection: Mat4f,
+	pub camera_pt: Point3d,
+	pub lights: &'a [Light],
+	/// Material of the triangles being drawn
+	pub material: &'a Material,
+}
+
+/// Model-space attributes of a vertex, the input of the vertex shader
+pub struct VtxAttr {
+	pub vtx_coords: Point3d,
+	pub norm_coords: Vector3d,
+	pub txt_coords: [f32; 2],
+}
+
+/// Values produced by the vertex shader for every vertex of a triangle. The rasterizer
+/// interpolates them across the triangle and hands the result to the fragment shader.
+/// The shaders agree on the layout, e.g. a shader pushing a point and then a vector
+/// reads them back with get_point3d(0) and get_vector3d(3).
+#[derive(Clone, Default)]
+pub struct Varying {
+	data: Vec<f32>,
+}
+
+impl Varying {
+	pub fn new() -> Self {
+		Varying {
+			data: Vec::new(),
+		}
+	}
+	pub fn len(&self) -> usize {
+		self.data.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.data.is_empty()
+	}
+	pub fn push(&mut self, val: f32) {
+		self.data.push(val);
+	}
+	pub fn push_point3d(&mut self, val: &Point3d) {
+		self.data.extend_from_slice(&[val.x, val.y, val.z]);
+	}
+	pub fn push_vector3d(&mut self, val: &Vector3d) {
+		self.data.extend_from_slice(&[val.x, val.y, val.z]);
+	}
+	pub fn get(&self, idx: usize) -> f32 {
+		self.data[idx]
+	}
+	pub fn get_point3d(&self, idx: usize) -> Point3d {
+		Point3d::from_coords(self.data[idx], self.data[idx + 1], self.data[idx + 2])
+	}
+	pub fn get_vector3d(&self, idx: usize) -> Vector3d {
+		Vector3d::from_coords(self.data[idx], self.data[idx + 1], self.data[idx + 2])
+	}
+
+	/// Overwrites self with the weighted sum of the vertex varyings. The weights are expected
+	/// to be perspective-corrected barycentric coordinates which add up to 1.
+	pub fn interpolate(&mut self, vtx: &[Varying; 3], weights: &[f32; 3]) {
+		self.data.clear();
+		self.data.extend(
+			vtx[0].data.iter()
+				.zip(vtx[1].data.iter())
+				.zip(vtx[2].data.iter())
+				.map(|((a, b), c)| a * weights[0] + b * weights[1] + c * weights[2])
+		);
+	}
+}
+
+pub trait VtxShader: Sync {
+	/// Returns the clip-space coordinates of the vertex, and stores the values to be
+	/// interpolated for the fragment shader in varying
+	fn vertex(&self, uniforms: &Uniforms, vtx: &VtxAttr, varying: &mut Varying) -> Point4d;
+}
+
+pub trait PixShader: Sync {
+	/// Returns the linear RGB color of the fragment from the interpolated varying
+	fn fragment(&self, uniforms: &Uniforms, varying: &Varying) -> [f32; 3];
+}
+
+/// Phong shading, the same one the ray tracer uses. Passes the world-space position,
+/// the normal and the texture coordinates from the vertex shader to the fragment shader.
+/// The colors come from the materials of the objects.
+pub struct PhongShader;
+
+impl VtxShader for PhongShader {
+	fn vertex(&self, uniforms: &Uniforms, vtx: &VtxAttr, varying: &mut Varying) -> Point4d {
+		let world_pt = &uniforms.model * Point4d::from(vtx.vtx_coords);
+		varying.push_point3d(&Point3d::from(world_pt));
+		varying.push_vector3d(&(&uniforms.model_normal * vtx.norm_coords));
+		varying.push(vtx.txt_coords[0]);
+		varying.push(vtx.txt_coords[1]);
+		&uniforms.projection * (&uniforms.view * world_pt)
+	}
+}
+
+impl PixShader for PhongShader {
+	fn fragment(&self, uniforms: &Uniforms, varying: &Varying) -> [f32; 3] {
+		let surface_pt = varying.get_point3d(0);
+		let surface_normal = varying.get_vector3d(3).normalize();
+		let albedo = uniforms.material.get_albedo(varying.get(6), varying.get(7));
+		// No shadows, the rasterizer has no way to tell what blocks the lights
+		shading::phong(surface_pt, uniforms.camera_pt, surface_normal, uniforms.lights, uniforms.material, albedo, &|_| 1.0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn interpolate_varying() {
+		let mut vtx = [Varying::new(), Varying::new(), Varying::new()];
+		vtx[0].push_point3d(&Point3d::from_coords(0.0, 0.0, 0.0));
+		vtx[1].push_point3d(&Point3d::from_coords(4.0, 0.0, 2.0));
+		vtx[2].push_point3d(&Point3d::from_coords(0.0, 8.0, -2.0));
+		vtx.iter_mut().for_each(|v| v.push(1.0));
+		let mut res = Varying::new();
+		res.interpolate(&vtx, &[0.5, 0.25, 0.25]);
+		assert_eq!(res.len(), 4);
+		let pt = res.get_point3d(0);
+		assert_eq!((pt.x, pt.y, pt.z), (1.0, 2.0, 0.0));
+		assert_eq!(res.get(3), 1.0);
+	}
+}